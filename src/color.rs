@@ -0,0 +1,280 @@
+use std::str::FromStr;
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ColorDepth {
+    /// no colors at all, bold, italic and underline are still used
+    NoColor,
+    /// the 8 basic colors and their bright variants
+    Ansi16,
+    /// the xterm 256 color palette
+    #[default]
+    Ansi256,
+    /// 24-bit RGB
+    TrueColor,
+}
+
+impl FromStr for ColorDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorDepth, String> {
+        match s {
+            "none" | "0" => Ok(ColorDepth::NoColor),
+            "16" => Ok(ColorDepth::Ansi16),
+            "256" => Ok(ColorDepth::Ansi256),
+            "truecolor" | "24bit" => Ok(ColorDepth::TrueColor),
+            _ => Err(format!(
+                "unknown color depth '{}', expected none, 16, 256 or truecolor",
+                s
+            )),
+        }
+    }
+}
+
+/// Escape setting the foreground (`layer` 38) or background (`layer` 48) to
+/// an RGB color, reduced to the nearest one available at `depth`.
+pub fn rgb(layer: u8, (r, g, b): (u8, u8, u8), depth: ColorDepth) -> String {
+    match depth {
+        ColorDepth::NoColor => String::new(),
+        ColorDepth::TrueColor => format!("\x1b[{};2;{};{};{}m", layer, r, g, b),
+        ColorDepth::Ansi256 => format!("\x1b[{};5;{}m", layer, ansi256(r, g, b)),
+        ColorDepth::Ansi16 => basic(layer, ansi16(r, g, b)),
+    }
+}
+
+/// Escape setting the foreground or background to entry `i` of the 256 color
+/// palette, which is reduced to the basic colors if that's all there is.
+pub fn indexed(layer: u8, i: u8, depth: ColorDepth) -> String {
+    match depth {
+        ColorDepth::NoColor => String::new(),
+        ColorDepth::Ansi16 if i < 16 => basic(layer, i),
+        ColorDepth::Ansi16 => rgb(layer, ansi256_rgb(i), depth),
+        _ => format!("\x1b[{};5;{}m", layer, i),
+    }
+}
+
+/// Escape setting the foreground or background back to the terminal's
+/// default, nothing if no color is used.
+pub fn reset(layer: u8, depth: ColorDepth) -> &'static str {
+    match (depth, layer) {
+        (ColorDepth::NoColor, _) => "",
+        (_, 48) => "\x1b[49m",
+        _ => "\x1b[39m",
+    }
+}
+
+/// SGR for one of the 16 basic colors, 30-37 and 90-97 for the foreground,
+/// 40-47 and 100-107 for the background.
+fn basic(layer: u8, i: u8) -> String {
+    let base = if i < 8 { layer - 8 } else { layer + 52 };
+    format!("\x1b[{}m", base + i % 8)
+}
+
+/// Closest of the 16 basic colors. Grays go by lightness and everything else
+/// by hue, so muted colors keep their tint instead of all turning gray; the
+/// bright variant is picked for light colors.
+pub fn ansi16(r: u8, g: u8, b: u8) -> u8 {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    if chroma < 40 {
+        return match (u16::from(max) + u16::from(min)) / 2 {
+            0..=63 => 0,
+            64..=159 => 8,
+            160..=223 => 7,
+            _ => 15,
+        };
+    }
+
+    let (r, g, b, c) = (f32::from(r), f32::from(g), f32::from(b), f32::from(chroma));
+    let max_f = f32::from(max);
+    let hue = if max_f == r {
+        60.0 * ((g - b) / c)
+    } else if max_f == g {
+        60.0 * ((b - r) / c + 2.0)
+    } else {
+        60.0 * ((r - g) / c + 4.0)
+    };
+    let hue = hue.rem_euclid(360.0);
+    // red, yellow, green, cyan, blue, magenta at 60 degree steps
+    let color = match ((hue + 30.0) / 60.0) as u8 % 6 {
+        0 => 1,
+        1 => 3,
+        2 => 2,
+        3 => 6,
+        4 => 4,
+        _ => 5,
+    };
+    if max > 0xe0 {
+        color + 8
+    } else {
+        color
+    }
+}
+
+/// Closest entry of the xterm 256 color palette, either in the 6x6x6 color
+/// cube or on the grayscale ramp.
+pub fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let cube = |c: u8| -> u8 {
+        if c < 48 {
+            0
+        } else if c < 115 {
+            1
+        } else {
+            (c - 35) / 40
+        }
+    };
+    let level = |i: u8| -> i32 {
+        if i == 0 {
+            0
+        } else {
+            55 + 40 * i32::from(i)
+        }
+    };
+    let (cr, cg, cb) = (cube(r), cube(g), cube(b));
+    let dist = |(r2, g2, b2): (i32, i32, i32)| {
+        let (dr, dg, db) = (i32::from(r) - r2, i32::from(g) - g2, i32::from(b) - b2);
+        dr * dr + dg * dg + db * db
+    };
+
+    let avg = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+    let gray = if avg > 238 {
+        23
+    } else {
+        (avg.saturating_sub(3) / 10) as u8
+    };
+    let gray_level = 8 + 10 * i32::from(gray);
+
+    if dist((gray_level, gray_level, gray_level)) < dist((level(cr), level(cg), level(cb))) {
+        232 + gray
+    } else {
+        16 + 36 * cr + 6 * cg + cb
+    }
+}
+
+/// RGB value of an entry in the xterm 256 color palette.
+pub fn ansi256_rgb(i: u8) -> (u8, u8, u8) {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    let level = |v: u8| if v == 0 { 0 } else { 55 + 40 * v };
+    match i {
+        0..=15 => BASE[i as usize],
+        16..=231 => {
+            let i = i - 16;
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + 10 * (i - 232);
+            (v, v, v)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_corners() {
+        for (rgb, i) in [
+            ((0, 0, 0), 16),
+            ((255, 255, 255), 231),
+            ((255, 0, 0), 196),
+            ((0, 0, 255), 21),
+            ((8, 8, 8), 232),
+            ((128, 128, 128), 244),
+            ((238, 238, 238), 255),
+        ] {
+            assert_eq!(ansi256(rgb.0, rgb.1, rgb.2), i, "{:?}", rgb);
+        }
+        for (i, rgb) in [
+            (0, (0, 0, 0)),
+            (15, (255, 255, 255)),
+            (16, (0, 0, 0)),
+            (208, (255, 135, 0)),
+            (231, (255, 255, 255)),
+            (232, (8, 8, 8)),
+            (255, (238, 238, 238)),
+        ] {
+            assert_eq!(ansi256_rgb(i), rgb, "{}", i);
+        }
+    }
+
+    // the solarized palette used to panic on overflow
+    #[test]
+    fn solarized() {
+        for (rgb, i) in [
+            ((0, 43, 54), 234),
+            ((7, 54, 66), 235),
+            ((88, 110, 117), 242),
+            ((147, 161, 161), 247),
+            ((253, 246, 227), 230),
+            ((181, 137, 0), 136),
+            ((203, 75, 22), 166),
+            ((211, 54, 130), 168),
+            ((108, 113, 196), 62),
+            ((38, 139, 210), 32),
+            ((42, 161, 152), 36),
+            ((133, 153, 0), 100),
+        ] {
+            assert_eq!(ansi256(rgb.0, rgb.1, rgb.2), i, "{:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn basic_colors() {
+        for (rgb, i) in [
+            ((0, 0, 0), 0),
+            ((128, 128, 128), 8),
+            ((192, 192, 192), 7),
+            ((255, 255, 255), 15),
+            ((128, 0, 0), 1),
+            ((255, 0, 0), 9),
+            ((0, 160, 0), 2),
+            ((181, 137, 0), 3),
+            ((0, 0, 205), 4),
+            ((160, 0, 160), 5),
+            ((42, 161, 152), 6),
+            ((0, 43, 54), 6),
+        ] {
+            assert_eq!(ansi16(rgb.0, rgb.1, rgb.2), i, "{:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn escapes_by_depth() {
+        assert_eq!(indexed(38, 208, ColorDepth::Ansi16), "\x1b[93m");
+        assert_eq!(indexed(48, 4, ColorDepth::Ansi16), "\x1b[44m");
+        assert_eq!(indexed(38, 208, ColorDepth::Ansi256), "\x1b[38;5;208m");
+        assert_eq!(
+            rgb(38, (1, 2, 3), ColorDepth::TrueColor),
+            "\x1b[38;2;1;2;3m"
+        );
+        assert_eq!(rgb(48, (255, 0, 0), ColorDepth::Ansi16), "\x1b[101m");
+        assert_eq!(rgb(38, (255, 0, 0), ColorDepth::Ansi256), "\x1b[38;5;196m");
+    }
+
+    #[test]
+    fn no_color_writes_nothing() {
+        assert_eq!(rgb(38, (255, 0, 0), ColorDepth::NoColor), "");
+        assert_eq!(indexed(48, 208, ColorDepth::NoColor), "");
+        assert_eq!(reset(38, ColorDepth::NoColor), "");
+        assert_eq!(reset(48, ColorDepth::NoColor), "");
+    }
+}
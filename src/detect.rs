@@ -0,0 +1,119 @@
+use mdt::ColorDepth;
+use std::{env, str::FromStr};
+
+/// When to use color, from `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// color if stdout is a terminal and the environment doesn't say otherwise
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorChoice, String> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "unknown color choice '{}', expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+/// Whether an environment variable is set to something other than empty.
+fn set(name: &str) -> bool {
+    env::var_os(name).is_some_and(|v| !v.is_empty())
+}
+
+/// Whether `CLICOLOR_FORCE` asks for color even if stdout isn't a terminal.
+pub fn forced() -> bool {
+    env::var("CLICOLOR_FORCE").is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Decides how to color the output. `NO_COLOR` turns color off and
+/// `CLICOLOR_FORCE` turns it on when stdout isn't a terminal, unless
+/// `choice` is `Always` or `Never`. `depth` overrides the depth detected
+/// from the terminal.
+pub fn color_depth(choice: ColorChoice, depth: Option<ColorDepth>, is_tty: bool) -> ColorDepth {
+    let on = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !set("NO_COLOR") && (is_tty || forced()),
+    };
+    if !on {
+        return ColorDepth::NoColor;
+    }
+    match depth {
+        Some(depth) => depth,
+        // asked for color on a terminal that claims to have none
+        None if choice == ColorChoice::Always => terminal_depth().max(ColorDepth::Ansi16),
+        None => terminal_depth(),
+    }
+}
+
+/// Colors the terminal supports, going by `COLORTERM` and `TERM`.
+pub fn terminal_depth() -> ColorDepth {
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorDepth::TrueColor;
+    }
+    let term = match env::var("TERM") {
+        Ok(term) => term,
+        Err(_) => return ColorDepth::NoColor,
+    };
+    if term.is_empty() || term == "dumb" {
+        ColorDepth::NoColor
+    } else if term.ends_with("-direct")
+        || term.contains("truecolor")
+        || term.contains("24bit")
+        || [
+            "xterm-kitty",
+            "xterm-ghostty",
+            "wezterm",
+            "alacritty",
+            "foot",
+        ]
+        .iter()
+        .any(|t| term.starts_with(t))
+    {
+        ColorDepth::TrueColor
+    } else if term.contains("256color") {
+        ColorDepth::Ansi256
+    } else {
+        ColorDepth::Ansi16
+    }
+}
+
+/// Columns and lines to lay the document out for. The width is `width` if
+/// given, then `COLUMNS`, then the terminal's, and 80 when there's no
+/// terminal to ask, e.g. when the output is redirected. The height is the
+/// terminal's or 24.
+pub fn size(width: Option<u16>) -> (u16, u16) {
+    let term = terminal_size();
+    let columns = env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.trim().parse().ok())
+        .filter(|&c| c > 0);
+    let width = width.or(columns).or(term.map(|(w, _)| w)).unwrap_or(80);
+    (width, term.map_or(24, |(_, h)| h))
+}
+
+/// Columns and lines of the screen the document is shown on, the terminal's
+/// own size whatever width it's laid out for. Falls back to `size(width)`
+/// when there's no terminal to ask.
+pub fn screen(width: Option<u16>) -> (u16, u16) {
+    terminal_size().unwrap_or_else(|| size(width))
+}
+
+fn terminal_size() -> Option<(u16, u16)> {
+    termion::terminal_size()
+        .ok()
+        .filter(|&(w, h)| w > 0 && h > 0)
+}
@@ -0,0 +1,213 @@
+use std::str::FromStr;
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// What to do with a document's front matter, the `---` YAML or `+++` TOML
+/// block static site generators keep metadata in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrontMatter {
+    /// left out
+    #[default]
+    Hide,
+    /// a table of its fields before the document
+    Table,
+    /// its `title` field in a banner before the document
+    Title,
+}
+
+impl FromStr for FrontMatter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FrontMatter, String> {
+        match s {
+            "hide" => Ok(FrontMatter::Hide),
+            "table" => Ok(FrontMatter::Table),
+            "title" => Ok(FrontMatter::Title),
+            _ => Err(format!(
+                "unknown front matter mode '{}', expected hide, table or title",
+                s
+            )),
+        }
+    }
+}
+
+/// Splits the front matter off the start of `input`, returning its fields
+/// and the rest of the document. A block that doesn't parse as a mapping of
+/// fields isn't front matter, just a document starting with a rule.
+pub fn split(input: &str) -> (Option<Vec<(String, String)>>, &str) {
+    let mut lines = input.split_inclusive('\n');
+    let fence = match lines.next().map(str::trim_end) {
+        Some(fence @ "---") | Some(fence @ "+++") => fence,
+        _ => return (None, input),
+    };
+    let mut len = input.find('\n').map_or(input.len(), |i| i + 1);
+    let start = len;
+    for line in lines {
+        let end = len;
+        len += line.len();
+        let line = line.trim_end();
+        // YAML may also be closed with "..."
+        if line == fence || (fence == "---" && line == "...") {
+            let block = &input[start..end];
+            let fields = if fence == "---" {
+                yaml(block)
+            } else {
+                toml(block)
+            };
+            return match fields {
+                Some(fields) => (Some(fields), &input[len..]),
+                None => (None, input),
+            };
+        }
+    }
+    (None, input)
+}
+
+/// The value of the `title` field, if there is one.
+pub fn title(fields: &[(String, String)]) -> Option<&str> {
+    fields
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("title"))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Markdown for a table of `fields`, to render in front of the document.
+pub fn table(fields: &[(String, String)]) -> String {
+    let mut md = String::from("| Field | Value |\n| --- | --- |\n");
+    for (key, value) in fields {
+        md.push_str(&format!("| {} | {} |\n", escape(key), escape(value)));
+    }
+    md.push('\n');
+    md
+}
+
+// backslash escapes every punctuation character so values are shown as they
+// are, not as markdown
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' => out.push(' '),
+            c if c.is_ascii_punctuation() => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn yaml(block: &str) -> Option<Vec<(String, String)>> {
+    let docs = YamlLoader::load_from_str(block).ok()?;
+    match docs.into_iter().next() {
+        // an empty block, or one with only comments
+        None => Some(Vec::new()),
+        Some(Yaml::Hash(hash)) => Some(
+            hash.iter()
+                .map(|(key, value)| (yaml_value(key), yaml_value(value)))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn yaml_value(value: &Yaml) -> String {
+    match *value {
+        Yaml::String(ref s) | Yaml::Real(ref s) => s.clone(),
+        Yaml::Integer(i) => i.to_string(),
+        Yaml::Boolean(b) => b.to_string(),
+        Yaml::Array(ref items) => items.iter().map(yaml_value).collect::<Vec<_>>().join(", "),
+        Yaml::Hash(ref hash) => hash
+            .iter()
+            .map(|(k, v)| format!("{}: {}", yaml_value(k), yaml_value(v)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
+
+fn toml(block: &str) -> Option<Vec<(String, String)>> {
+    let table: toml::value::Table = toml::from_str(block).ok()?;
+    Some(
+        table
+            .iter()
+            .map(|(key, value)| (key.clone(), toml_value(value)))
+            .collect(),
+    )
+}
+
+fn toml_value(value: &toml::Value) -> String {
+    match *value {
+        toml::Value::String(ref s) => s.clone(),
+        toml::Value::Array(ref items) => {
+            items.iter().map(toml_value).collect::<Vec<_>>().join(", ")
+        }
+        toml::Value::Table(ref table) => table
+            .iter()
+            .map(|(k, v)| format!("{}: {}", k, toml_value(v)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        ref other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split;
+
+    fn fields(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn splits_yaml_and_toml() {
+        let yaml = "---\ntitle: Hello\ntags: [a, b]\n---\n# Doc\n";
+        assert_eq!(
+            split(yaml),
+            (fields(&[("title", "Hello"), ("tags", "a, b")]), "# Doc\n")
+        );
+        let toml = "+++\ntitle = \"Hello\"\ndraft = true\n+++\n# Doc\n";
+        assert_eq!(
+            split(toml),
+            (fields(&[("draft", "true"), ("title", "Hello")]), "# Doc\n")
+        );
+    }
+
+    #[test]
+    fn yaml_may_close_with_dots() {
+        let input = "---\ntitle: Hello\n...\nText\n";
+        assert_eq!(split(input), (fields(&[("title", "Hello")]), "Text\n"));
+        // but TOML may not
+        let input = "+++\ntitle = \"Hello\"\n...\nText\n";
+        assert_eq!(split(input), (None, input));
+    }
+
+    #[test]
+    fn handles_crlf_line_endings() {
+        let input = "---\r\ntitle: Hello\r\n---\r\nText\r\n";
+        assert_eq!(split(input), (fields(&[("title", "Hello")]), "Text\r\n"));
+    }
+
+    #[test]
+    fn empty_blocks_are_front_matter() {
+        assert_eq!(split("---\n---\nText\n"), (fields(&[]), "Text\n"));
+        assert_eq!(split("+++\n+++\nText\n"), (fields(&[]), "Text\n"));
+    }
+
+    #[test]
+    fn anything_else_is_left_alone() {
+        for input in [
+            "---\nJust a paragraph between rules.\n---\nText\n",
+            "---\n- a\n- b\n---\n",
+            "---\ntitle: never closed\n",
+            "Text\n---\ntitle: Hello\n---\n",
+        ] {
+            assert_eq!(split(input), (None, input), "{:?}", input);
+        }
+    }
+}
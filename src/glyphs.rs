@@ -0,0 +1,74 @@
+/// Characters marking up everything but tables: list bullets, task
+/// checkboxes, blockquote bars and footnote references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyphs {
+    /// list bullets, one per level of nesting and repeating after the last
+    pub bullets: [char; 3],
+    /// task list checkboxes
+    pub unchecked: &'static str,
+    pub checked: &'static str,
+    /// bar down the side of blockquotes, one per level of nesting
+    pub quote: char,
+    /// footnotes are marked with superscript digits rather than `[^n]`
+    pub superscript: bool,
+    /// leads the list of places a footnote was referenced from
+    pub back: &'static str,
+}
+
+impl Glyphs {
+    pub const ASCII: Glyphs = Glyphs {
+        bullets: ['*', '-', '+'],
+        unchecked: "[ ]",
+        checked: "[x]",
+        quote: '>',
+        superscript: false,
+        back: "<-",
+    };
+
+    pub const UNICODE: Glyphs = Glyphs {
+        bullets: ['•', '◦', '▪'],
+        unchecked: "☐",
+        checked: "☑",
+        quote: '│',
+        superscript: true,
+        back: "↩",
+    };
+
+    /// Bullet for an item of a list nested `level` deep, counting from 1.
+    pub fn bullet(&self, level: usize) -> char {
+        self.bullets[level.saturating_sub(1) % self.bullets.len()]
+    }
+
+    /// Marks a reference to footnote `number`, and its definition.
+    pub fn footnote_mark(&self, number: usize) -> String {
+        const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+        if !self.superscript {
+            return format!("[^{}]", number);
+        }
+        number
+            .to_string()
+            .chars()
+            .filter_map(|d| d.to_digit(10))
+            .map(|d| DIGITS[d as usize])
+            .collect()
+    }
+}
+
+impl Default for Glyphs {
+    fn default() -> Glyphs {
+        Glyphs::UNICODE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glyphs;
+
+    #[test]
+    fn marks_follow_the_style() {
+        assert_eq!(Glyphs::ASCII.footnote_mark(12), "[^12]");
+        assert_eq!(Glyphs::UNICODE.footnote_mark(12), "¹²");
+        assert_eq!(Glyphs::ASCII.bullet(4), '*');
+        assert_eq!(Glyphs::UNICODE.bullet(2), '◦');
+    }
+}
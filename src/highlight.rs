@@ -0,0 +1,43 @@
+use crate::MDResult;
+use std::{env, path::PathBuf};
+use syntect::{highlighting::ThemeSet, parsing::SyntaxSet};
+
+/// Directory extra syntaxes and themes are read from, `$XDG_CONFIG_HOME/mdt`
+/// or `~/.config/mdt`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("mdt"))
+}
+
+fn user_dir(name: &str) -> Option<PathBuf> {
+    config_dir()
+        .map(|dir| dir.join(name))
+        .filter(|dir| dir.is_dir())
+}
+
+/// The bundled syntaxes plus any `.sublime-syntax` files in the `syntaxes`
+/// directory of `config_dir`.
+pub fn syntaxes() -> MDResult<SyntaxSet> {
+    let defaults = SyntaxSet::load_defaults_newlines();
+    match user_dir("syntaxes") {
+        Some(dir) => {
+            let mut builder = defaults.into_builder();
+            builder.add_from_folder(dir, true)?;
+            Ok(builder.build())
+        }
+        None => Ok(defaults),
+    }
+}
+
+/// The bundled themes plus any `.tmTheme` files in the `themes` directory of
+/// `config_dir`.
+pub fn themes() -> MDResult<ThemeSet> {
+    let mut themes = ThemeSet::load_defaults();
+    if let Some(dir) = user_dir("themes") {
+        themes.add_from_folder(dir)?;
+    }
+    Ok(themes)
+}
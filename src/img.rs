@@ -1,10 +1,56 @@
-use immeta;
-use std::convert::AsRef;
-// use std::fs::File;
-// use std::io::BufReader;
-use std::path::Path;
+use image::{imageops::FilterType, Rgba, RgbaImage};
+use std::{convert::AsRef, fmt::Write, fs, path::Path, str::FromStr};
 
-use super::MDResult;
+use super::{
+    color::{self, ansi256, ansi256_rgb, ColorDepth},
+    MDResult,
+};
+
+/// How images are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Graphics {
+    /// unicode half blocks, works everywhere with color
+    #[default]
+    Blocks,
+    /// DEC sixel graphics (xterm, foot, WezTerm, mlterm)
+    Sixel,
+    /// kitty graphics protocol
+    Kitty,
+    /// iTerm2 inline images (also WezTerm)
+    Iterm,
+}
+
+impl FromStr for Graphics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Graphics, String> {
+        match s {
+            "blocks" => Ok(Graphics::Blocks),
+            "sixel" => Ok(Graphics::Sixel),
+            "kitty" => Ok(Graphics::Kitty),
+            "iterm" => Ok(Graphics::Iterm),
+            _ => Err(format!(
+                "unknown graphics mode '{}', expected blocks, sixel, kitty or iterm",
+                s
+            )),
+        }
+    }
+}
+
+/// Pixel size of a terminal cell as reported by the tty, or a common default
+/// when the terminal doesn't say.
+pub fn cell_size() -> (u32, u32) {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0;
+    if ok && ws.ws_col > 0 && ws.ws_row > 0 && ws.ws_xpixel > 0 && ws.ws_ypixel > 0 {
+        (
+            u32::from(ws.ws_xpixel / ws.ws_col),
+            u32::from(ws.ws_ypixel / ws.ws_row),
+        )
+    } else {
+        (8, 16)
+    }
+}
 
 pub fn img_dim<P>(path: P) -> MDResult<(u32, u32)>
 where
@@ -13,3 +59,337 @@ where
     let dim = immeta::load_from_file(path.as_ref())?.dimensions();
     Ok((dim.width, dim.height))
 }
+
+/// Size in terminal cells (columns, lines) to draw an image of `dim` pixels
+/// with half blocks, keeping its aspect ratio and fitting inside `max`. Every
+/// cell holds two vertically stacked pixels.
+pub fn fit((w, h): (u32, u32), (max_cols, max_lines): (u32, u32)) -> (u32, u32) {
+    if w == 0 || h == 0 {
+        return (0, 0);
+    }
+    let mut cols = w.min(max_cols.max(1));
+    let mut rows = (u64::from(h) * u64::from(cols) / u64::from(w)).max(1) as u32;
+    let max_rows = max_lines.max(1) * 2;
+    if rows > max_rows {
+        cols = (u64::from(cols) * u64::from(max_rows) / u64::from(rows)).max(1) as u32;
+        rows = max_rows;
+    }
+    (cols, rows.div_ceil(2))
+}
+
+/// Size in pixels to draw an image of `dim` pixels with a graphics protocol,
+/// never upscaling and fitting inside `max` (columns, lines) of `cell` sized
+/// cells.
+pub fn fit_pixels(
+    (w, h): (u32, u32),
+    (cell_w, cell_h): (u32, u32),
+    (max_cols, max_lines): (u32, u32),
+) -> (u32, u32) {
+    if w == 0 || h == 0 {
+        return (0, 0);
+    }
+    let (max_w, max_h) = (
+        u64::from(max_cols.max(1) * cell_w),
+        u64::from(max_lines.max(1) * cell_h),
+    );
+    let (mut pw, mut ph) = (u64::from(w), u64::from(h));
+    if pw > max_w {
+        ph = (ph * max_w / pw).max(1);
+        pw = max_w;
+    }
+    if ph > max_h {
+        pw = (pw * max_h / ph).max(1);
+        ph = max_h;
+    }
+    (pw as u32, ph as u32)
+}
+
+/// Renders the image at `path` to fit inside `max` (columns, lines), drawn
+/// with `graphics`. Comes back as one string per line the image covers: block
+/// art line by line, the graphics protocols as an escape sequence the
+/// terminal draws across all of them followed by empty lines, so that line
+/// counts match the screen. `cell` is the pixel size of a terminal cell.
+pub fn render<P>(
+    path: P,
+    graphics: Graphics,
+    max: (u32, u32),
+    cell: (u32, u32),
+    depth: ColorDepth,
+) -> MDResult<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let dim = img_dim(path)?;
+    if graphics == Graphics::Blocks {
+        return blocks(path, fit(dim, max), depth);
+    }
+
+    let (pw, ph) = fit_pixels(dim, cell, max);
+    let cells = (pw.div_ceil(cell.0), ph.div_ceil(cell.1));
+    let seq = match graphics {
+        Graphics::Iterm => iterm(&fs::read(path)?, cells),
+        Graphics::Kitty => kitty(&decode(path, (pw, ph))?, cells),
+        _ => sixel(&decode(path, (pw, ph))?),
+    };
+    let mut lines = vec![seq];
+    lines.resize(cells.1.max(1) as usize, String::new());
+    Ok(lines)
+}
+
+fn decode(path: &Path, (w, h): (u32, u32)) -> MDResult<RgbaImage> {
+    Ok(image::open(path)?
+        .resize_exact(w, h, FilterType::Triangle)
+        .to_rgba8())
+}
+
+/// iTerm2 inline image escape for the raw bytes of an image file, the
+/// terminal decodes and scales it into `cells` (columns, lines).
+pub fn iterm(data: &[u8], (cols, lines): (u32, u32)) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+        data.len(),
+        cols,
+        lines,
+        base64::encode(data)
+    )
+}
+
+/// kitty graphics protocol escapes transmitting `img` as raw RGBA and placing
+/// it over `cells` (columns, lines). The payload is sent in 4096 byte chunks,
+/// responses from the terminal are suppressed and the cursor isn't moved.
+pub fn kitty(img: &RgbaImage, (cols, lines): (u32, u32)) -> String {
+    const CHUNK: usize = 4096;
+    let payload = base64::encode(img.as_raw());
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(CHUNK).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,q=2,f=32,s={},v={},c={},r={},C=1,m={};",
+                img.width(),
+                img.height(),
+                cols,
+                lines,
+                more
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={};", more);
+        }
+        // base64 is always ascii
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+/// DEC sixel escape for `img`, with colors mapped onto the 256 color palette.
+/// Transparent pixels are left unpainted.
+pub fn sixel(img: &RgbaImage) -> String {
+    let (w, h) = img.dimensions();
+    let idx: Vec<Option<u8>> = img
+        .pixels()
+        .map(|p| {
+            if opaque(p) {
+                Some(ansi256(p.0[0], p.0[1], p.0[2]))
+            } else {
+                None
+            }
+        })
+        .collect();
+    let mut used = [false; 256];
+    for &i in idx.iter().flatten() {
+        used[i as usize] = true;
+    }
+    let colors: Vec<u8> = (0..=255u8).filter(|&i| used[i as usize]).collect();
+
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", w, h);
+    for &c in &colors {
+        let (r, g, b) = ansi256_rgb(c);
+        let pct = |v: u8| u32::from(v) * 100 / 255;
+        let _ = write!(out, "#{};2;{};{};{}", c, pct(r), pct(g), pct(b));
+    }
+    for band in (0..h).step_by(6) {
+        let mut first = true;
+        for &c in &colors {
+            let row: Vec<u8> = (0..w)
+                .map(|x| {
+                    (0..6)
+                        .filter(|dy| {
+                            let y = band + dy;
+                            y < h && idx[(y * w + x) as usize] == Some(c)
+                        })
+                        .fold(0, |bits, dy| bits | 1 << dy)
+                })
+                .collect();
+            if row.iter().all(|&bits| bits == 0) {
+                continue;
+            }
+            if !first {
+                // back to the start of the band for the next color
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{}", c);
+            push_sixels(&mut out, &row);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+// run length encoded sixel data
+fn push_sixels(out: &mut String, row: &[u8]) {
+    let mut i = 0;
+    while i < row.len() {
+        let run = row[i..].iter().take_while(|&&b| b == row[i]).count();
+        let c = char::from(63 + row[i]);
+        if run > 3 {
+            let _ = write!(out, "!{}{}", run, c);
+        } else {
+            out.extend(std::iter::repeat_n(c, run));
+        }
+        i += run;
+    }
+}
+
+/// Renders the image at `path` as `size` (columns, lines) of half block
+/// characters, with colors reduced to what the terminal can show at `depth`.
+fn blocks(path: &Path, (cols, lines): (u32, u32), depth: ColorDepth) -> MDResult<Vec<String>> {
+    let img = decode(path, (cols, lines * 2))?;
+
+    let mut out = Vec::with_capacity(lines as usize);
+    for y in 0..lines {
+        let mut line = String::new();
+        for x in 0..cols {
+            let top = img.get_pixel(x, y * 2);
+            let bottom = if y * 2 + 1 < img.height() {
+                Some(img.get_pixel(x, y * 2 + 1))
+            } else {
+                None
+            };
+            // the pixel in the lower half may be missing or transparent
+            let bottom = bottom.filter(|p| opaque(p));
+            match (opaque(top), bottom) {
+                (true, Some(bottom)) => {
+                    push_color(&mut line, top, false, depth);
+                    push_color(&mut line, bottom, true, depth);
+                    line.push('▀');
+                }
+                (true, None) => {
+                    push_color(&mut line, top, false, depth);
+                    line.push('▀');
+                }
+                (false, Some(bottom)) => {
+                    push_color(&mut line, bottom, false, depth);
+                    line.push('▄');
+                }
+                (false, None) => line.push(' '),
+            }
+            line.push_str("\x1b[m");
+        }
+        out.push(line);
+    }
+    Ok(out)
+}
+
+fn opaque(p: &Rgba<u8>) -> bool {
+    p.0[3] >= 128
+}
+
+fn push_color(s: &mut String, p: &Rgba<u8>, bg: bool, depth: ColorDepth) {
+    let [r, g, b, _] = p.0;
+    let layer = if bg { 48 } else { 38 };
+    s.push_str(&color::rgb(layer, (r, g, b), depth));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // red and green on top, blue and a transparent pixel below
+    fn tiny() -> RgbaImage {
+        RgbaImage::from_raw(
+            2,
+            2,
+            vec![255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 0, 0, 0, 0],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sixel_bytes() {
+        assert_eq!(
+            sixel(&tiny()),
+            "\x1bP0;1;0q\"1;1;2;2\
+             #21;2;0;0;100#46;2;0;100;0#196;2;100;0;0\
+             #21A?$#46?@$#196@?-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn sixel_runs() {
+        let mut out = String::new();
+        push_sixels(&mut out, &[1, 1, 1, 1, 1, 2, 2, 0]);
+        assert_eq!(out, "!5@AA?");
+    }
+
+    #[test]
+    fn kitty_bytes() {
+        assert_eq!(
+            kitty(&tiny(), (1, 1)),
+            "\x1b_Ga=T,q=2,f=32,s=2,v=2,c=1,r=1,C=1,m=0;/wAA/wD/AP8AAP//AAAAAA==\x1b\\"
+        );
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        // 3072 bytes of pixels make exactly one 4096 byte chunk of base64
+        let one = kitty(&RgbaImage::new(32, 24), (4, 2));
+        assert!(one.starts_with("\x1b_Ga=T,q=2,f=32,s=32,v=24,c=4,r=2,C=1,m=0;"));
+        assert_eq!(one.matches("\x1b_G").count(), 1);
+
+        // 4096 bytes make 5464 of base64, split 4096 + 1368
+        let two = kitty(&RgbaImage::new(32, 32), (4, 2));
+        let chunks: Vec<&str> = two.split_terminator("\x1b\\").collect();
+        assert_eq!(chunks.len(), 2);
+        let (head, payload) = chunks[0].split_at(chunks[0].find(';').unwrap() + 1);
+        assert_eq!(head, "\x1b_Ga=T,q=2,f=32,s=32,v=32,c=4,r=2,C=1,m=1;");
+        assert_eq!(payload.len(), 4096);
+        assert_eq!(&chunks[1][..7], "\x1b_Gm=0;");
+        assert_eq!(chunks[1].len() - 7, 1368);
+    }
+
+    #[test]
+    fn iterm_bytes() {
+        assert_eq!(
+            iterm(b"\x89PNG", (2, 1)),
+            "\x1b]1337;File=inline=1;size=4;width=2;height=1;preserveAspectRatio=1:iVBORw==\x07"
+        );
+    }
+
+    #[test]
+    fn images_take_a_line_per_row() {
+        let path = std::env::temp_dir().join(format!("mdt-img-{}.png", std::process::id()));
+        RgbaImage::new(32, 40).save(&path).unwrap();
+        // 8x16 pixel cells make 4x3 cells
+        for graphics in [Graphics::Sixel, Graphics::Kitty, Graphics::Iterm] {
+            let lines = render(&path, graphics, (80, 24), (8, 16), ColorDepth::TrueColor).unwrap();
+            assert_eq!(lines.len(), 3, "{:?}", graphics);
+            assert!(lines[0].starts_with('\x1b'));
+            assert!(lines[1..].iter().all(String::is_empty));
+        }
+        let blocks = render(
+            &path,
+            Graphics::Blocks,
+            (80, 24),
+            (8, 16),
+            ColorDepth::TrueColor,
+        );
+        assert!(!blocks.unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
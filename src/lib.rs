@@ -0,0 +1,110 @@
+#[macro_use]
+extern crate lazy_static;
+
+use self::MarkdownError::*;
+use std::{error::Error, fmt, io, path::PathBuf};
+
+pub mod color;
+pub mod front_matter;
+mod glyphs;
+pub mod highlight;
+mod img;
+pub mod links;
+pub mod outline;
+mod renderer;
+mod table;
+mod terminal;
+pub mod text;
+pub mod theme;
+pub use crate::{
+    color::ColorDepth,
+    front_matter::FrontMatter,
+    img::Graphics,
+    links::Links,
+    outline::Outline,
+    renderer::{Renderer, RendererBuilder, TableStyle},
+    text::Line,
+    theme::Theme,
+};
+
+// Error
+#[derive(Debug)]
+pub enum MarkdownError {
+    Io(io::Error),
+    /// a file that couldn't be read
+    File(PathBuf, io::Error),
+    Img(immeta::Error),
+    Decode(image::ImageError),
+    Opt(String),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    Syntax(syntect::LoadingError),
+}
+
+pub type MDResult<T = ()> = Result<T, MarkdownError>;
+
+impl From<io::Error> for MarkdownError {
+    fn from(e: io::Error) -> MarkdownError {
+        Io(e)
+    }
+}
+
+impl From<immeta::Error> for MarkdownError {
+    fn from(e: immeta::Error) -> MarkdownError {
+        Img(e)
+    }
+}
+
+impl From<image::ImageError> for MarkdownError {
+    fn from(e: image::ImageError) -> MarkdownError {
+        Decode(e)
+    }
+}
+
+impl From<toml::de::Error> for MarkdownError {
+    fn from(e: toml::de::Error) -> MarkdownError {
+        Toml(e)
+    }
+}
+
+impl From<serde_json::Error> for MarkdownError {
+    fn from(e: serde_json::Error) -> MarkdownError {
+        Json(e)
+    }
+}
+
+impl From<syntect::LoadingError> for MarkdownError {
+    fn from(e: syntect::LoadingError) -> MarkdownError {
+        Syntax(e)
+    }
+}
+
+impl fmt::Display for MarkdownError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Io(ref e) => write!(f, "IO Error: {}", e),
+            File(ref path, ref e) => write!(f, "{}: {}", path.display(), e),
+            Img(ref e) => write!(f, "Image Load Error: {}", e),
+            Decode(ref e) => write!(f, "Image Decode Error: {}", e),
+            Opt(ref e) => write!(f, "Invalid Option: {}", e),
+            Toml(ref e) => write!(f, "Theme Error: {}", e),
+            Json(ref e) => write!(f, "Theme Error: {}", e),
+            Syntax(ref e) => write!(f, "Syntax Load Error: {}", e),
+        }
+    }
+}
+
+impl Error for MarkdownError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Io(ref e) => Some(e),
+            File(_, ref e) => Some(e),
+            Img(ref e) => Some(e),
+            Decode(ref e) => Some(e),
+            Opt(_) => None,
+            Toml(ref e) => Some(e),
+            Json(ref e) => Some(e),
+            Syntax(ref e) => Some(e),
+        }
+    }
+}
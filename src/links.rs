@@ -0,0 +1,170 @@
+use std::str::FromStr;
+use url::Url;
+
+/// How links are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Links {
+    /// the destination in parentheses after the text
+    Inline,
+    /// a number after the text, destinations are listed at the end
+    #[default]
+    Footnote,
+    /// clickable text using OSC 8 hyperlinks
+    Osc8,
+    /// clickable text and numbered footnotes
+    Both,
+}
+
+impl FromStr for Links {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Links, String> {
+        match s {
+            "inline" => Ok(Links::Inline),
+            "footnote" => Ok(Links::Footnote),
+            "osc8" => Ok(Links::Osc8),
+            "both" => Ok(Links::Both),
+            _ => Err(format!(
+                "unknown link mode '{}', expected inline, footnote, osc8 or both",
+                s
+            )),
+        }
+    }
+}
+
+/// The links of a document, numbered in the order their destinations first
+/// appear. Links to the same place share a number.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    /// relative destinations are resolved against it
+    base: Option<Url>,
+    /// destination and title, link `n` is at `n - 1`
+    links: Vec<(String, String)>,
+}
+
+impl Registry {
+    pub fn new(base: Option<Url>) -> Registry {
+        Registry {
+            base,
+            links: Vec::new(),
+        }
+    }
+
+    /// `dest` joined onto the base URL, as is if it's absolute already or
+    /// there is no base.
+    pub fn resolve(&self, dest: &str) -> String {
+        match self.base {
+            Some(ref base) if Url::parse(dest).is_err() => base
+                .join(dest)
+                .map_or_else(|_| dest.to_string(), String::from),
+            _ => dest.to_string(),
+        }
+    }
+
+    /// Number of the link to `dest`, registering it if it's new. The first
+    /// title given for a destination is the one kept.
+    pub fn add(&mut self, dest: &str, title: &str) -> usize {
+        let dest = self.resolve(dest);
+        match self.links.iter().position(|(d, _)| *d == dest) {
+            Some(i) => {
+                if self.links[i].1.is_empty() {
+                    self.links[i].1 = title.to_string();
+                }
+                i + 1
+            }
+            None => {
+                self.links.push((dest, title.to_string()));
+                self.links.len()
+            }
+        }
+    }
+
+    /// Resolved destination of link `number`.
+    pub fn dest(&self, number: usize) -> &str {
+        &self.links[number - 1].0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Number, destination and title of every link.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str, &str)> {
+        self.links
+            .iter()
+            .enumerate()
+            .map(|(i, (dest, title))| (i + 1, dest.as_str(), title.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Registry;
+    use url::Url;
+
+    fn links(registry: &Registry) -> Vec<(usize, &str, &str)> {
+        registry.iter().collect()
+    }
+
+    #[test]
+    fn same_destination_shares_a_number() {
+        let mut registry = Registry::default();
+        assert_eq!(registry.add("http://a.org/", ""), 1);
+        assert_eq!(registry.add("http://b.org/", ""), 2);
+        assert_eq!(registry.add("http://a.org/", ""), 1);
+        assert_eq!(registry.dest(2), "http://b.org/");
+        assert_eq!(
+            links(&registry),
+            [(1, "http://a.org/", ""), (2, "http://b.org/", "")]
+        );
+    }
+
+    #[test]
+    fn first_title_is_kept() {
+        let mut registry = Registry::default();
+        registry.add("http://a.org/", "");
+        registry.add("http://a.org/", "First");
+        registry.add("http://a.org/", "Second");
+        assert_eq!(links(&registry), [(1, "http://a.org/", "First")]);
+    }
+
+    #[test]
+    fn resolves_against_the_base() {
+        let base = Url::parse("https://example.com/docs/guide.md").unwrap();
+        let registry = Registry::new(Some(base));
+        assert_eq!(
+            registry.resolve("intro.md"),
+            "https://example.com/docs/intro.md"
+        );
+        assert_eq!(
+            registry.resolve("../README.md"),
+            "https://example.com/README.md"
+        );
+        assert_eq!(registry.resolve("/top"), "https://example.com/top");
+        assert_eq!(
+            registry.resolve("#usage"),
+            "https://example.com/docs/guide.md#usage"
+        );
+        assert_eq!(registry.resolve("http://other.org/"), "http://other.org/");
+        assert_eq!(
+            registry.resolve("mailto:me@example.com"),
+            "mailto:me@example.com"
+        );
+    }
+
+    #[test]
+    fn resolved_destinations_are_shared() {
+        let base = Url::parse("https://example.com/docs/").unwrap();
+        let mut registry = Registry::new(Some(base));
+        assert_eq!(registry.add("a.md", ""), 1);
+        assert_eq!(registry.add("https://example.com/docs/a.md", ""), 1);
+        assert_eq!(registry.dest(1), "https://example.com/docs/a.md");
+    }
+
+    #[test]
+    fn without_a_base_destinations_are_kept() {
+        let registry = Registry::default();
+        assert_eq!(registry.resolve("intro.md"), "intro.md");
+        assert_eq!(registry.resolve("#usage"), "#usage");
+    }
+}
@@ -1,24 +1,34 @@
-#[macro_use]
-extern crate lazy_static;
+mod detect;
+mod pager;
+mod watch;
 
-// Any type that derives Fail can be cast into Error
-use self::MarkdownError::*;
+use crate::{detect::ColorChoice, pager::Pager, watch::Watcher};
 use getopts::Options as GetOpts;
-use pulldown_cmark::{Options, Parser, OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use mdt::{
+    highlight,
+    text::Indent,
+    ColorDepth, FrontMatter, Graphics, Links, MDResult,
+    MarkdownError::{self, Opt},
+    Outline, Renderer, TableStyle, Theme,
+};
 use std::{
-    env,
-    error::Error,
-    fmt,
-    fs::File,
-    io::{self, Read, Stdout},
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
 };
+use termion::{clear, cursor};
+use url::Url;
 
-mod img;
-pub mod table;
-pub mod terminal;
-pub use crate::terminal::{MDParser, TermAscii, TermUnicode};
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
 
-fn main() -> MDResult {
+fn run() -> MDResult {
     // parse args
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
@@ -27,41 +37,368 @@ fn main() -> MDResult {
     opts.optflag(
         "t",
         "truecolor",
-        "print with truecolor (syntax highlighting)",
+        "print with truecolor, same as --color-depth truecolor",
+    );
+    opts.optopt(
+        "",
+        "color",
+        "when to use color: auto (default), always or never",
+        "WHEN",
+    );
+    opts.optopt(
+        "",
+        "color-depth",
+        "colors the terminal supports: none, 16, 256 or truecolor, detected by default",
+        "DEPTH",
     );
     opts.optflag("a", "ascii", "print table using ascii characters");
+    opts.optopt(
+        "",
+        "theme",
+        "colors to use: dark (default), light or a TOML/JSON theme file",
+        "THEME",
+    );
+    opts.optopt(
+        "",
+        "code-theme",
+        "syntax highlighting theme for code blocks, see --list-themes",
+        "NAME",
+    );
+    opts.optflag(
+        "",
+        "list-themes",
+        "list the syntax highlighting themes and exit",
+    );
+    opts.optflag(
+        "",
+        "list-syntaxes",
+        "list the languages code blocks can be highlighted as and exit",
+    );
+    opts.optflag(
+        "p",
+        "plain",
+        "no colors or styles, the default when output isn't a terminal",
+    );
+    opts.optopt(
+        "g",
+        "graphics",
+        "draw images with blocks (default), sixel, kitty or iterm",
+        "MODE",
+    );
+    opts.optopt(
+        "",
+        "links",
+        "show links as footnote (default), inline, osc8 (clickable) or both",
+        "MODE",
+    );
+    opts.optopt(
+        "",
+        "front-matter",
+        "show YAML/TOML front matter: hide (default), table or title",
+        "MODE",
+    );
+    opts.optopt("", "base-url", "resolve relative links against URL", "URL");
+    opts.optflag(
+        "",
+        "task-summary",
+        "follow task lists with how many of their tasks are done",
+    );
+    opts.optopt(
+        "w",
+        "width",
+        "columns to wrap to, instead of COLUMNS or the terminal's width",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-width",
+        "wrap to at most N columns on wide terminals",
+        "N",
+    );
+    opts.optflag(
+        "",
+        "center",
+        "center the --max-width column on the terminal",
+    );
+    opts.optflag(
+        "",
+        "watch",
+        "render the file again whenever it changes, until interrupted",
+    );
+    opts.optflag(
+        "",
+        "index",
+        "list the files found and ask which one to show",
+    );
+    opts.optflag("", "toc", "print a table of contents before the document");
+    opts.optopt(
+        "s",
+        "section",
+        "jump to the section under HEADING, given by number (2.1) or title",
+        "HEADING",
+    );
+    opts.optflag(
+        "P",
+        "no-pager",
+        "never page output, even if it's taller than the terminal",
+    );
     opts.optflag("h", "help", "print this help menu");
-    let matches = opts.parse(&args[1..])?;
+    let matches = opts.parse(&args[1..]).map_err(|e| Opt(e.to_string()))?;
     if matches.opt_present("h") {
         print_usage(&program, &opts);
         return Ok(());
     }
-    let truecolor = matches.opt_present("t");
+    let choice = match matches.opt_str("color") {
+        Some(when) => when.parse().map_err(Opt)?,
+        None => ColorChoice::default(),
+    };
+    let depth = match matches.opt_str("color-depth") {
+        Some(depth) => Some(depth.parse().map_err(Opt)?),
+        None if matches.opt_present("t") => Some(ColorDepth::TrueColor),
+        None => None,
+    };
+    // plain text when redirected, unless color is asked for
+    let is_tty = termion::is_tty(&io::stdout());
+    let plain = matches.opt_present("p")
+        || (!is_tty
+            && match choice {
+                ColorChoice::Always => false,
+                ColorChoice::Never => true,
+                ColorChoice::Auto => !detect::forced(),
+            });
+    let depth = if plain {
+        ColorDepth::NoColor
+    } else {
+        detect::color_depth(choice, depth, is_tty)
+    };
+    let graphics = match matches.opt_str("g") {
+        Some(mode) => mode.parse().map_err(Opt)?,
+        None => Graphics::default(),
+    };
+    let links = match matches.opt_str("links") {
+        Some(mode) => mode.parse().map_err(Opt)?,
+        None => Links::default(),
+    };
+    let front_matter = match matches.opt_str("front-matter") {
+        Some(mode) => mode.parse().map_err(Opt)?,
+        None => FrontMatter::default(),
+    };
+    let base_url = match matches.opt_str("base-url") {
+        Some(base) => Some(
+            Url::parse(&base).map_err(|e| Opt(format!("invalid base url '{}': {}", base, e)))?,
+        ),
+        None => None,
+    };
+    let width = match matches.opt_str("w") {
+        Some(n) => Some(columns(&n)?),
+        None => None,
+    };
+    let max_width = match matches.opt_str("max-width") {
+        Some(n) => Some(columns(&n)?),
+        None => None,
+    };
+    if matches.opt_present("center") && max_width.is_none() {
+        return Err(Opt("--center needs --max-width".to_string()));
+    }
+    let mut theme = match matches.opt_str("theme") {
+        Some(name) => Theme::load(&name)?,
+        None => Theme::default(),
+    };
 
-    // get input
+    // syntax highlighting, extended from the config directory
+    let syntax_set = highlight::syntaxes()?;
+    let theme_set = highlight::themes()?;
+    if matches.opt_present("list-themes") {
+        for name in theme_set.themes.keys() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    if matches.opt_present("list-syntaxes") {
+        let mut syntaxes: Vec<_> = syntax_set.syntaxes().iter().filter(|s| !s.hidden).collect();
+        syntaxes.sort_by_key(|s| s.name.to_lowercase());
+        for syntax in syntaxes {
+            println!("{} ({})", syntax.name, syntax.file_extensions.join(", "));
+        }
+        return Ok(());
+    }
+    if let Some(name) = matches.opt_str("code-theme") {
+        theme.code_theme = name;
+    }
+    if !theme_set.themes.contains_key(&theme.code_theme) {
+        return Err(Opt(format!(
+            "unknown code theme '{}', see --list-themes",
+            theme.code_theme
+        )));
+    }
+
+    // get input, stdin unless there are files or directories to read
+    let files = markdown_files(&matches.free)?;
+    if !matches.free.is_empty() && files.is_empty() {
+        return Err(Opt(format!(
+            "no markdown files in {}",
+            matches.free.join(", ")
+        )));
+    }
+    let watch = matches.opt_present("watch");
+    if files.is_empty() && watch {
+        return Err(Opt("--watch needs a file to watch".to_string()));
+    }
+    if files.is_empty() && matches.opt_present("index") {
+        return Err(Opt(
+            "--index needs files or directories to pick from".to_string()
+        ));
+    }
+    let files = if matches.opt_present("index") {
+        vec![pick(&files)?]
+    } else {
+        files
+    };
     let mut input = String::new();
-    if matches.free.is_empty() {
+    if files.is_empty() {
         io::stdin().read_to_string(&mut input)?;
+    }
+
+    // wide terminals get a narrower column of text, centered on the screen
+    // if asked for
+    let term_size = detect::size(width);
+    let screen = detect::screen(width);
+    let width = max_width.map_or(term_size.0, |max| max.min(term_size.0));
+    let margin = if matches.opt_present("center") {
+        screen.0.saturating_sub(width) / 2
     } else {
-        File::open(&matches.free[0])?.read_to_string(&mut input)?;
+        0
+    };
+    let table_style = if matches.opt_present("a") {
+        TableStyle::Ascii
+    } else {
+        TableStyle::Unicode
+    };
+    let mut builder = Renderer::builder()
+        .width(width)
+        .margin(margin)
+        .height(term_size.1)
+        .color(depth)
+        .table_style(table_style)
+        .graphics(graphics)
+        .theme(theme)
+        .plain(plain)
+        .links(links)
+        .front_matter(front_matter)
+        .task_summary(matches.opt_present("task-summary"))
+        .highlighting(syntax_set, theme_set);
+    if let Some(base) = base_url {
+        builder = builder.base_url(base);
     }
 
-    // parser options
-    let mut opts = Options::empty();
-    opts.insert(OPTION_ENABLE_TABLES);
-    opts.insert(OPTION_ENABLE_FOOTNOTES);
+    // images are looked up relative to the file they're in
+    let docs: Vec<(Option<&PathBuf>, Renderer)> = if files.is_empty() {
+        vec![(None, builder.build())]
+    } else {
+        files
+            .iter()
+            .map(|path| {
+                let root = path
+                    .parent()
+                    .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+                (Some(path), builder.clone().root(root).build())
+            })
+            .collect()
+    };
 
-    // make parser
-    let p = Parser::new_ext(&input, opts);
-    let term_size = termion::terminal_size()?;
+    let toc = matches.opt_present("toc");
+    // files are read again for every render while watching them, each one
+    // under a banner with its name when there's more than one
+    let load = || -> MDResult<(String, Outline)> {
+        let read = |path: Option<&PathBuf>| match path {
+            Some(path) => {
+                fs::read_to_string(path).map_err(|e| MarkdownError::File(path.clone(), e))
+            }
+            None => Ok(input.clone()),
+        };
+        if let [(path, ref renderer)] = docs[..] {
+            return render(renderer, &read(path)?, toc, margin, depth);
+        }
+        let mut out = String::new();
+        let mut outline = Outline::default();
+        for (path, renderer) in &docs {
+            let title = path.map(|p| p.display().to_string()).unwrap_or_default();
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            let line = out.lines().count();
+            let mut banner = Vec::new();
+            renderer.banner(&title, &mut banner)?;
+            out.push_str(&String::from_utf8_lossy(&banner));
+            let (doc, part) = render(renderer, &read(*path)?, toc, margin, depth)?;
+            outline.append(title, part, line);
+            out.push_str(&doc);
+        }
+        Ok((out, outline))
+    };
+    let (doc, outline) = load()?;
+    let section = match matches.opt_str("s") {
+        Some(heading) => Some(
+            outline
+                .find(&heading)
+                .ok_or_else(|| Opt(format!("no heading matching '{}'", heading)))?,
+        ),
+        None => None,
+    };
 
-    // dynamic
-    let mut terminal: Box<dyn MDParser<Parser, Stdout>> = if matches.opt_present("a") {
-        Box::new(TermAscii::new(term_size, truecolor))
+    // page the document if it doesn't fit on screen, or if it might not
+    // once it changes
+    let page =
+        is_tty && !matches.opt_present("P") && (watch || doc.lines().count() > screen.1 as usize);
+    if page {
+        let mut pager = Pager::new(&doc, screen, outline);
+        if let Some(idx) = section {
+            pager.goto(idx);
+        }
+        if watch {
+            let mut watchers: Vec<Watcher> = files.iter().map(Watcher::new).collect();
+            pager.run_watching(POLL, |pager| {
+                if !changed(&mut watchers) {
+                    return false;
+                }
+                match load() {
+                    Ok((doc, outline)) => pager.reload(&doc, outline),
+                    Err(e) => pager.error(e.to_string()),
+                }
+                true
+            })?;
+        } else {
+            pager.run()?;
+        }
     } else {
-        Box::new(TermUnicode::new(term_size, truecolor))
-    };
-    terminal.parse(p, &mut io::stdout())?;
+        // stop quietly once the reader is gone, e.g. `mdt README.md | head`
+        if !print(&doc, &outline, section)? {
+            return Ok(());
+        }
+        if watch {
+            // redraw the whole screen on every change until interrupted
+            let mut watchers: Vec<Watcher> = files.iter().map(Watcher::new).collect();
+            loop {
+                thread::sleep(POLL);
+                if !changed(&mut watchers) {
+                    continue;
+                }
+                if is_tty {
+                    print!("{}{}", clear::All, cursor::Goto(1, 1));
+                    io::stdout().flush()?;
+                }
+                match load() {
+                    Ok((doc, outline)) => {
+                        let section = matches.opt_str("s").and_then(|h| outline.find(&h));
+                        if !print(&doc, &outline, section)? {
+                            return Ok(());
+                        }
+                    }
+                    Err(e) => eprintln!("error: {}", e),
+                }
+            }
+        }
+    }
     // static
     // if matches.opt_present("a") {
     //     let mut terminal = TermAscii::new(term_size, truecolor);
@@ -73,58 +410,167 @@ fn main() -> MDResult {
     Ok(())
 }
 
-fn print_usage(program: &str, opts: &GetOpts) {
-    let brief = format!("Usage: {} FILE [options]", program);
-    print!("{}", opts.usage(&brief));
-}
+/// How often a watched file is checked for changes.
+const POLL: Duration = Duration::from_millis(250);
 
-// Error
-#[derive(Debug)]
-pub enum MarkdownError {
-    Io(io::Error),
-    Args(getopts::Fail),
-    Img(immeta::Error),
+/// Whether any of the watched files changed, checking all of them.
+fn changed(watchers: &mut [Watcher]) -> bool {
+    let mut changed = false;
+    for watcher in watchers {
+        changed |= watcher.changed();
+    }
+    changed
 }
 
-pub type MDResult<T = ()> = Result<T, MarkdownError>;
+/// The markdown files named in `args`, with directories replaced by the
+/// `.md` and `.markdown` files under them in sorted order. This runs once,
+/// files added to a directory later aren't picked up by `--watch`.
+fn markdown_files(args: &[String]) -> MDResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for arg in args {
+        let path = PathBuf::from(arg);
+        if path.is_dir() {
+            find_markdown(&path, &mut files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
 
-impl From<io::Error> for MarkdownError {
-    fn from(e: io::Error) -> MarkdownError {
-        Io(e)
+fn find_markdown(dir: &Path, files: &mut Vec<PathBuf>) -> MDResult {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        // hidden files and directories such as .git are skipped, symlinks
+        // aren't followed so they can't loop
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            find_markdown(&path, files)?;
+        } else if kind.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| ext == "md" || ext == "markdown")
+        {
+            files.push(path);
+        }
     }
+    Ok(())
 }
 
-impl From<getopts::Fail> for MarkdownError {
-    fn from(e: getopts::Fail) -> MarkdownError {
-        Args(e)
+/// Lists `files` numbered and asks which one to show.
+fn pick(files: &[PathBuf]) -> MDResult<PathBuf> {
+    let width = files.len().to_string().len();
+    for (i, file) in files.iter().enumerate() {
+        println!("{:>2$}. {}", i + 1, file.display(), width);
+    }
+    print!("file to show (1-{}): ", files.len());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    match answer.trim().parse::<usize>() {
+        Ok(n) if n >= 1 && n <= files.len() => Ok(files[n - 1].clone()),
+        _ => Err(Opt(format!("no file numbered '{}'", answer.trim()))),
     }
 }
 
-impl From<immeta::Error> for MarkdownError {
-    fn from(e: immeta::Error) -> MarkdownError {
-        Img(e)
+/// Renders `input`, after a table of contents if `toc` is set.
+fn render(
+    renderer: &Renderer,
+    input: &str,
+    toc: bool,
+    margin: u16,
+    depth: ColorDepth,
+) -> MDResult<(String, Outline)> {
+    let mut out = Vec::new();
+    let mut outline = renderer.render(input, &mut out)?;
+    if toc && !outline.is_empty() {
+        let mut toc = Vec::new();
+        outline.write_toc(&mut Indent::new(&mut toc, margin as usize), depth)?;
+        outline.offset(toc.iter().filter(|&&b| b == b'\n').count());
+        toc.append(&mut out);
+        out = toc;
     }
+    Ok((String::from_utf8_lossy(&out).into_owned(), outline))
 }
 
-impl fmt::Display for MarkdownError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Io(ref e) => write!(f, "IO Error: {}", e),
-            Args(ref e) => write!(f, "Arg Parse Error: {}", e),
-            Img(ref e) => write!(f, "Image Load Error: {}", e),
+/// Writes `doc` to stdout, or only the section under heading `section`.
+/// Returns false if stdout was closed before all of it was written.
+fn print(doc: &str, outline: &Outline, section: Option<usize>) -> MDResult<bool> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let written = match section {
+        Some(idx) => {
+            let start = outline.headings()[idx].line;
+            let end = outline.section_end(idx).unwrap_or(usize::MAX);
+            doc.lines()
+                .take(end)
+                .skip(start)
+                .try_for_each(|line| writeln!(stdout, "{}", line))
         }
+        None => stdout.write_all(doc.as_bytes()),
+    }
+    .and_then(|_| stdout.flush());
+    match written {
+        Ok(()) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e.into()),
     }
 }
 
-impl Error for MarkdownError {
-    fn description(&self) -> &str {
-        match *self {
-            Io(ref e) => e.description(),
-            Args(ref e) => e.description(),
-            Img(ref e) => e.description(),
-        }
+/// A column count given on the command line.
+fn columns(n: &str) -> MDResult<u16> {
+    match n.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(Opt(format!(
+            "invalid width '{}', expected a number of columns",
+            n
+        ))),
     }
-    fn cause(&self) -> Option<&Error> {
-        None
+}
+
+fn print_usage(program: &str, opts: &GetOpts) {
+    let brief = format!("Usage: {} [FILE|DIR]... [options]", program);
+    print!("{}", opts.usage(&brief));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::markdown_files;
+    use std::{env, fs, process};
+
+    #[test]
+    fn finds_markdown_sorted_and_recursively() {
+        let dir = env::temp_dir().join(format!("mdt-files-{}", process::id()));
+        for file in [
+            "b.md",
+            "a.markdown",
+            "notes.txt",
+            ".hidden.md",
+            ".git/x.md",
+            "sub/c.md",
+            "sub/.draft.md",
+        ] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "# Doc\n").unwrap();
+        }
+
+        let args = [
+            dir.display().to_string(),
+            dir.join("notes.txt").display().to_string(),
+        ];
+        let files = markdown_files(&args).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|f| f.strip_prefix(&dir).unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["a.markdown", "b.md", "sub/c.md", "notes.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
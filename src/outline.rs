@@ -0,0 +1,130 @@
+use crate::color::{indexed, reset, ColorDepth};
+use std::io::{Result, Write};
+
+/// A heading of the rendered document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: i32,
+    /// position among its siblings, e.g. "2.1" for the first subsection of
+    /// the second section
+    pub number: String,
+    pub title: String,
+    /// line of the rendered output the heading is on
+    pub line: usize,
+}
+
+/// The headings of a document in order, numbered by how they nest.
+#[derive(Debug, Clone, Default)]
+pub struct Outline {
+    headings: Vec<Heading>,
+    /// levels of the headings enclosing the next one
+    open: Vec<i32>,
+    counters: Vec<usize>,
+}
+
+impl Outline {
+    pub fn push(&mut self, level: i32, title: String, line: usize) {
+        while self.open.last().is_some_and(|&open| open >= level) {
+            self.open.pop();
+        }
+        self.open.push(level);
+        let depth = self.open.len();
+        self.counters.resize(depth, 0);
+        self.counters[depth - 1] += 1;
+
+        let number = self
+            .counters
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+        self.headings.push(Heading {
+            level,
+            number,
+            title,
+            line,
+        });
+    }
+
+    pub fn headings(&self) -> &[Heading] {
+        &self.headings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headings.is_empty()
+    }
+
+    /// Moves every heading down by `lines`, for when something is printed
+    /// before the document.
+    pub fn offset(&mut self, lines: usize) {
+        for heading in &mut self.headings {
+            heading.line += lines;
+        }
+    }
+
+    /// Adds `other` as the next of several documents, printed after a one
+    /// line `title` banner on line `line`. The banner becomes a level 0
+    /// heading numbered by document, with the headings of `other` numbered
+    /// under it: "2.1" is the first section of the second document.
+    pub fn append(&mut self, title: String, other: Outline, line: usize) {
+        let doc = self.headings.iter().filter(|h| h.level == 0).count() + 1;
+        self.headings.push(Heading {
+            level: 0,
+            number: doc.to_string(),
+            title,
+            line,
+        });
+        for mut heading in other.headings {
+            heading.number = format!("{}.{}", doc, heading.number);
+            heading.line += line + 1;
+            self.headings.push(heading);
+        }
+    }
+
+    /// Index of the heading numbered `query`, or else the first one whose
+    /// title contains it, ignoring case.
+    pub fn find(&self, query: &str) -> Option<usize> {
+        let query = query.trim().trim_end_matches('.');
+        if query.is_empty() {
+            return None;
+        }
+        let lower = query.to_lowercase();
+        self.headings
+            .iter()
+            .position(|h| h.number == query)
+            .or_else(|| {
+                self.headings
+                    .iter()
+                    .position(|h| h.title.to_lowercase().contains(&lower))
+            })
+    }
+
+    /// Line the section started by heading `idx` ends on (exclusive), `None`
+    /// if it runs to the end of the document.
+    pub fn section_end(&self, idx: usize) -> Option<usize> {
+        let level = self.headings[idx].level;
+        self.headings[idx + 1..]
+            .iter()
+            .find(|h| h.level <= level)
+            .map(|h| h.line)
+    }
+
+    /// Writes a numbered table of contents, indented by nesting.
+    pub fn write_toc<W: Write>(&self, w: &mut W, depth: ColorDepth) -> Result<()> {
+        let (yellow, plain) = (indexed(38, 3, depth), reset(38, depth));
+        writeln!(w, "{}Contents{}", yellow, plain)?;
+        for heading in &self.headings {
+            let depth = heading.number.matches('.').count();
+            writeln!(
+                w,
+                "{}{}{}{} {}",
+                "  ".repeat(depth + 1),
+                yellow,
+                heading.number,
+                plain,
+                heading.title
+            )?;
+        }
+        writeln!(w)
+    }
+}
@@ -0,0 +1,438 @@
+use mdt::{
+    text::{chunks, strip_escapes, Chunk},
+    Outline,
+};
+use std::{
+    io::{self, Write},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+use termion::{
+    clear, cursor, event::Key, input::TermRead, raw::IntoRawMode, screen::AlternateScreen, style,
+};
+
+/// Full screen viewer for a rendered document with less-like key bindings.
+pub struct Pager {
+    lines: Vec<String>,
+    /// `lines` without escapes, what searches run against
+    plain: Vec<String>,
+    top: usize,
+    size: (u16, u16),
+    search: Option<String>,
+    /// line of the match `n` and `N` step on from
+    current: Option<usize>,
+    /// prompt being typed ('/' for a search, ':' for a heading), its input
+    /// and where the view was before it started
+    prompt: Option<(char, String, usize)>,
+    outline: Outline,
+    /// shown in place of the status line, e.g. why the document couldn't be
+    /// reloaded
+    error: Option<String>,
+}
+
+impl Pager {
+    pub fn new(text: &str, size: (u16, u16), outline: Outline) -> Pager {
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        let plain = lines.iter().map(|l| strip_escapes(l)).collect();
+        Pager {
+            lines,
+            plain,
+            top: 0,
+            size,
+            search: None,
+            current: None,
+            prompt: None,
+            outline,
+            error: None,
+        }
+    }
+
+    /// Scrolls to heading number `idx` of the outline.
+    pub fn goto(&mut self, idx: usize) {
+        if let Some(heading) = self.outline.headings().get(idx) {
+            let line = heading.line;
+            self.scroll_to(line);
+        }
+    }
+
+    /// Runs the pager on the alternate screen until the user quits, reading
+    /// keys from the controlling terminal so input can still be piped in.
+    pub fn run(&mut self) -> io::Result<()> {
+        let tty = termion::get_tty()?;
+        let mut screen = AlternateScreen::from(io::stdout().into_raw_mode()?);
+        write!(screen, "{}", cursor::Hide)?;
+        self.draw(&mut screen)?;
+        for key in tty.keys() {
+            if !self.key(key?) {
+                break;
+            }
+            self.draw(&mut screen)?;
+        }
+        write!(screen, "{}", cursor::Show)?;
+        screen.flush()
+    }
+
+    /// Like `run`, but calls `poll` every `interval` to pick up a new version
+    /// of the document. `poll` returns whether it changed anything to redraw.
+    pub fn run_watching<F>(&mut self, interval: Duration, mut poll: F) -> io::Result<()>
+    where
+        F: FnMut(&mut Pager) -> bool,
+    {
+        // keys come from a thread of their own so polling isn't held up
+        let tty = termion::get_tty()?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for key in tty.keys() {
+                if tx.send(key).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut screen = AlternateScreen::from(io::stdout().into_raw_mode()?);
+        write!(screen, "{}", cursor::Hide)?;
+        self.draw(&mut screen)?;
+        loop {
+            match rx.recv_timeout(interval) {
+                Ok(key) => {
+                    if !self.key(key?) {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if !poll(self) {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.draw(&mut screen)?;
+        }
+        write!(screen, "{}", cursor::Show)?;
+        screen.flush()
+    }
+
+    /// Replaces the document, keeping the view where it was as far as the
+    /// new one goes.
+    pub fn reload(&mut self, text: &str, outline: Outline) {
+        self.lines = text.lines().map(String::from).collect();
+        self.plain = self.lines.iter().map(|l| strip_escapes(l)).collect();
+        self.outline = outline;
+        self.error = None;
+        self.scroll_to(self.top);
+    }
+
+    /// Shows `msg` instead of the status line until the next reload.
+    pub fn error(&mut self, msg: String) {
+        self.error = Some(msg);
+    }
+
+    // rows available for the document, the last one is the status line
+    fn rows(&self) -> usize {
+        (self.size.1 as usize).saturating_sub(1).max(1)
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.rows())
+    }
+
+    fn scroll_to(&mut self, top: usize) {
+        self.top = top.min(self.max_top());
+    }
+
+    fn scroll_by(&mut self, lines: isize) {
+        let top = if lines < 0 {
+            self.top.saturating_sub(lines.unsigned_abs())
+        } else {
+            self.top + lines as usize
+        };
+        self.scroll_to(top);
+    }
+
+    /// Handles a key press, returns false when the pager should exit.
+    fn key(&mut self, key: Key) -> bool {
+        if self.prompt.is_some() {
+            self.prompt_key(key);
+            return true;
+        }
+        let page = self.rows() as isize;
+        match key {
+            Key::Char('q') | Key::Ctrl('c') | Key::Esc => return false,
+            Key::Char('j') | Key::Down | Key::Char('\n') => self.scroll_by(1),
+            Key::Char('k') | Key::Up => self.scroll_by(-1),
+            Key::PageDown | Key::Char(' ') | Key::Ctrl('f') => self.scroll_by(page),
+            Key::PageUp | Key::Char('b') | Key::Ctrl('b') => self.scroll_by(-page),
+            Key::Ctrl('d') => self.scroll_by(page / 2),
+            Key::Ctrl('u') => self.scroll_by(-page / 2),
+            Key::Char('g') | Key::Home => self.scroll_to(0),
+            Key::Char('G') | Key::End => self.scroll_to(self.max_top()),
+            Key::Char(c @ '/') | Key::Char(c @ ':') => {
+                self.prompt = Some((c, String::new(), self.top))
+            }
+            Key::Char('n') => self.find_next(true),
+            Key::Char('N') => self.find_next(false),
+            Key::Char(']') => self.next_heading(true),
+            Key::Char('[') => self.next_heading(false),
+            _ => {}
+        }
+        true
+    }
+
+    // typing a search, the view follows the first match as the query grows.
+    // headings are looked up by number or title once the input is complete
+    fn prompt_key(&mut self, key: Key) {
+        let (kind, mut query, start) = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        match key {
+            Key::Char('\n') if kind == ':' => {
+                if let Some(idx) = self.outline.find(&query) {
+                    self.goto(idx);
+                }
+                return;
+            }
+            Key::Char('\n') => {
+                self.search = if query.is_empty() { None } else { Some(query) };
+                return;
+            }
+            Key::Esc | Key::Ctrl('c') => {
+                if kind == '/' {
+                    self.search = None;
+                    self.current = None;
+                }
+                self.top = start;
+                return;
+            }
+            Key::Backspace => {
+                query.pop();
+            }
+            Key::Char(c) => query.push(c),
+            _ => {}
+        }
+        if kind == '/' {
+            self.search = if query.is_empty() {
+                None
+            } else {
+                Some(query.clone())
+            };
+            self.top = start;
+            self.current = None;
+            self.find(start, true);
+        }
+        self.prompt = Some((kind, query, start));
+    }
+
+    /// Scrolls to the first heading below the top of the view, or the last
+    /// one above it.
+    fn next_heading(&mut self, forward: bool) {
+        let top = self.top;
+        let mut lines = self.outline.headings().iter().map(|h| h.line);
+        let line = if forward {
+            lines.find(|&line| line > top)
+        } else {
+            lines.rfind(|&line| line < top)
+        };
+        if let Some(line) = line {
+            self.scroll_to(line);
+        }
+    }
+
+    /// Moves to the match after or before the current one, or searches from
+    /// the top of the view if there isn't one yet.
+    fn find_next(&mut self, forward: bool) {
+        let len = self.lines.len();
+        let from = match self.current {
+            Some(line) if forward => line + 1,
+            Some(line) => (line % len.max(1) + len).saturating_sub(1),
+            None => self.top,
+        };
+        self.find(from, forward);
+    }
+
+    /// Makes the next line matching the search the current match, looking
+    /// forwards or backwards from line `from` and wrapping around the
+    /// document, and scrolls it into view.
+    fn find(&mut self, from: usize, forward: bool) {
+        let query = match self.search {
+            Some(ref query) => query,
+            None => return,
+        };
+        let len = self.lines.len();
+        if len == 0 {
+            return;
+        }
+        let found = (0..len)
+            .map(|i| {
+                if forward {
+                    (from + i) % len
+                } else {
+                    (from + len - i % len) % len
+                }
+            })
+            .find(|&i| !matches(&self.plain[i], query).is_empty());
+        if let Some(line) = found {
+            self.current = Some(line);
+            if line < self.top || line >= self.top + self.rows() {
+                self.scroll_to(line);
+            }
+        }
+    }
+
+    fn draw<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write!(w, "{}", clear::All)?;
+        let rows = self.rows();
+        for (row, line) in self.lines.iter().skip(self.top).take(rows).enumerate() {
+            write!(w, "{}", cursor::Goto(1, row as u16 + 1))?;
+            match self.search {
+                Some(ref query) => write!(w, "{}", highlight(line, query))?,
+                None => write!(w, "{}", line)?,
+            }
+            write!(w, "{}", style::Reset)?;
+        }
+
+        write!(w, "{}", cursor::Goto(1, rows as u16 + 1))?;
+        match self.prompt {
+            Some((kind, ref query, _)) => write!(w, "{}{}", kind, query)?,
+            None if self.error.is_some() => write!(
+                w,
+                "{}{}{}",
+                style::Invert,
+                self.error.as_deref().unwrap_or_default(),
+                style::Reset
+            )?,
+            None => {
+                let last = (self.top + rows).min(self.lines.len());
+                let percent = if self.lines.is_empty() {
+                    100
+                } else {
+                    last * 100 / self.lines.len()
+                };
+                write!(
+                    w,
+                    "{}lines {}-{}/{} ({}%){}",
+                    style::Invert,
+                    self.top + 1,
+                    last,
+                    self.lines.len(),
+                    percent,
+                    style::Reset
+                )?;
+            }
+        }
+        w.flush()
+    }
+}
+
+/// Char ranges in `line` matching `query`. The search is case insensitive
+/// unless the query has an uppercase letter.
+fn matches(line: &str, query: &str) -> Vec<(usize, usize)> {
+    let fold = !query.chars().any(char::is_uppercase);
+    let norm = |c: char| {
+        if fold {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let hay: Vec<char> = line.chars().map(norm).collect();
+    let needle: Vec<char> = query.chars().map(norm).collect();
+    if needle.is_empty() || needle.len() > hay.len() {
+        return Vec::new();
+    }
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i + needle.len() <= hay.len() {
+        if hay[i..i + needle.len()] == needle[..] {
+            found.push((i, i + needle.len()));
+            i += needle.len();
+        } else {
+            i += 1;
+        }
+    }
+    found
+}
+
+/// `line` with every match of `query` in reverse video, leaving its own
+/// escapes in place.
+fn highlight(line: &str, query: &str) -> String {
+    let found = matches(&strip_escapes(line), query);
+    if found.is_empty() {
+        return line.to_string();
+    }
+    let mut out = String::with_capacity(line.len());
+    let mut pos = 0;
+    for chunk in chunks(line) {
+        match chunk {
+            Chunk::Esc(e) => out.push_str(e),
+            Chunk::Text(t) => {
+                for c in t.chars() {
+                    if found.iter().any(|&(start, _)| start == pos) {
+                        out.push_str("\x1b[7m");
+                    }
+                    out.push(c);
+                    pos += 1;
+                    if found.iter().any(|&(_, end)| end == pos) {
+                        out.push_str("\x1b[27m");
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 30 lines with matches on 5, 24 and 27, in a view of 10 rows
+    fn pager() -> Pager {
+        let text: Vec<String> = (0..30)
+            .map(|i| match i {
+                5 | 24 | 27 => format!("line {} match", i),
+                _ => format!("line {}", i),
+            })
+            .collect();
+        Pager::new(&text.join("\n"), (80, 11), Outline::default())
+    }
+
+    fn search(pager: &mut Pager, query: &str) {
+        pager.key(Key::Char('/'));
+        for c in query.chars() {
+            pager.key(Key::Char(c));
+        }
+        pager.key(Key::Char('\n'));
+    }
+
+    #[test]
+    fn next_match_moves_past_the_last_page() {
+        let mut pager = pager();
+        search(&mut pager, "match");
+        assert_eq!(pager.current, Some(5));
+        let mut seen = vec![];
+        for _ in 0..4 {
+            pager.key(Key::Char('n'));
+            let line = pager.current.unwrap();
+            assert!(line >= pager.top && line < pager.top + pager.rows());
+            seen.push(line);
+        }
+        assert_eq!(seen, [24, 27, 5, 24]);
+        pager.key(Key::Char('N'));
+        assert_eq!(pager.current, Some(5));
+        pager.key(Key::Char('N'));
+        assert_eq!(pager.current, Some(27));
+    }
+
+    #[test]
+    fn escape_drops_the_search() {
+        let mut pager = pager();
+        pager.key(Key::Char('/'));
+        pager.key(Key::Char('m'));
+        assert_eq!(pager.search.as_deref(), Some("m"));
+        pager.key(Key::Esc);
+        assert_eq!(pager.search, None);
+        assert_eq!(pager.current, None);
+        assert_eq!(pager.top, 0);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::{
+    color::ColorDepth,
+    front_matter::{self, FrontMatter},
+    glyphs::Glyphs,
+    img::Graphics,
+    links::Links,
+    outline::Outline,
+    table::{AsciiTable, Table, UnicodeTable},
+    terminal::{MDParser, Terminal},
+    text::{self, Line},
+    theme::Theme,
+    MDResult,
+};
+use pulldown_cmark::{Options, Parser};
+use std::{borrow::Cow, fmt::Debug, io::Write, path::PathBuf, sync::Arc};
+use syntect::{highlighting::ThemeSet, parsing::SyntaxSet};
+use termion::style;
+use url::Url;
+
+/// Characters tables are drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableStyle {
+    #[default]
+    Unicode,
+    Ascii,
+}
+
+impl TableStyle {
+    /// Characters for the rest of the document that go with the tables.
+    pub(crate) fn glyphs(self) -> Glyphs {
+        match self {
+            TableStyle::Unicode => Glyphs::UNICODE,
+            TableStyle::Ascii => Glyphs::ASCII,
+        }
+    }
+}
+
+/// Renders markdown for a terminal, configured once with
+/// `Renderer::builder()` and reusable for any number of documents.
+///
+/// ```no_run
+/// use mdt::{ColorDepth, Renderer, TableStyle};
+///
+/// let renderer = Renderer::builder()
+///     .width(80)
+///     .color(ColorDepth::Ansi256)
+///     .table_style(TableStyle::Ascii)
+///     .build();
+/// print!("{}", renderer.render_to_string("# Usage\n\nRun `mdt FILE`.").unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    size: (u16, u16),
+    margin: u16,
+    depth: ColorDepth,
+    table_style: TableStyle,
+    theme: Theme,
+    graphics: Graphics,
+    root: PathBuf,
+    plain: bool,
+    links: Links,
+    front_matter: FrontMatter,
+    base_url: Option<Url>,
+    task_summary: bool,
+    highlighting: Option<(Arc<SyntaxSet>, Arc<ThemeSet>)>,
+}
+
+impl Renderer {
+    pub fn builder() -> RendererBuilder {
+        RendererBuilder::default()
+    }
+
+    /// Renders `input` to `w`, returning the headings it has and the lines
+    /// they're on.
+    pub fn render<W: Write>(&self, input: &str, w: &mut W) -> MDResult<Outline> {
+        // front matter isn't markdown, it's shown as a table or banner if at all
+        let (fields, body) = front_matter::split(input);
+        let mut source = Cow::Borrowed(body);
+        let mut banner = false;
+        match (self.front_matter, fields) {
+            (FrontMatter::Table, Some(ref fields)) if !fields.is_empty() => {
+                source = Cow::Owned(front_matter::table(fields) + body);
+            }
+            (FrontMatter::Title, Some(ref fields)) => {
+                if let Some(title) = front_matter::title(fields) {
+                    self.banner(title, w)?;
+                    banner = true;
+                }
+            }
+            _ => {}
+        }
+
+        let mut opts = Options::empty();
+        opts.insert(Options::ENABLE_TABLES);
+        opts.insert(Options::ENABLE_FOOTNOTES);
+        opts.insert(Options::ENABLE_TASKLISTS);
+        opts.insert(Options::ENABLE_STRIKETHROUGH);
+        let parser = Parser::new_ext(&source, opts);
+
+        let mut w = text::Indent::new(w, self.margin as usize);
+        let mut outline = match self.table_style {
+            TableStyle::Unicode => self.render_with::<UnicodeTable, _>(parser, &mut w)?,
+            TableStyle::Ascii => self.render_with::<AsciiTable, _>(parser, &mut w)?,
+        };
+        if banner {
+            outline.offset(1);
+        }
+        Ok(outline)
+    }
+
+    /// Renders `input` into a string.
+    pub fn render_to_string(&self, input: &str) -> MDResult<String> {
+        let mut out = Vec::new();
+        self.render(input, &mut out)?;
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+
+    /// Renders `input` into lines, each with its width on screen, for
+    /// laying the output out with other text.
+    pub fn render_lines(&self, input: &str) -> MDResult<Vec<Line>> {
+        Ok(text::lines(&self.render_to_string(input)?))
+    }
+
+    /// Writes a line across the page with `title` in it, setting apart the
+    /// document that follows, e.g. with the name of its file.
+    pub fn banner<W: Write>(&self, title: &str, w: &mut W) -> MDResult {
+        let bar = match self.table_style {
+            TableStyle::Unicode => "─",
+            TableStyle::Ascii => "-",
+        };
+        let rest = (self.size.0 as usize).saturating_sub(text::display_width(title) + 4);
+        let (rule, name) = (
+            self.theme.rule.start(self.depth),
+            self.theme.h1.start(self.depth),
+        );
+        let mut w = text::Indent::new(text::Strip::new(w, self.plain), self.margin as usize);
+        writeln!(
+            w,
+            "{}{}{} {}{}{} {}{}{}",
+            rule,
+            bar.repeat(2),
+            style::Reset,
+            name,
+            title,
+            style::Reset,
+            rule,
+            bar.repeat(rest),
+            style::Reset
+        )?;
+        Ok(())
+    }
+
+    fn render_with<'a, T, W>(&self, parser: Parser<'a>, w: &mut W) -> MDResult<Outline>
+    where
+        T: Table<'a> + Debug,
+        W: Write,
+    {
+        let mut terminal = Terminal::<T>::new(self.size, self.depth)
+            .with_glyphs(self.table_style.glyphs())
+            .with_root(self.root.clone())
+            .with_graphics(self.graphics)
+            .with_theme(self.theme.clone())
+            .with_plain(self.plain)
+            .with_links(self.links)
+            .with_base_url(self.base_url.clone())
+            .with_task_summary(self.task_summary);
+        if let Some((ref syntax_set, ref theme_set)) = self.highlighting {
+            terminal = terminal.with_highlighting(syntax_set.clone(), theme_set.clone());
+        }
+        terminal.parse(parser, w)?;
+        Ok(MDParser::<Parser, W>::outline(&terminal).clone())
+    }
+}
+
+/// Settings for a `Renderer`. Unset ones default to an 80x24 terminal with
+/// 256 colors, unicode tables and the dark theme.
+#[derive(Debug, Clone)]
+pub struct RendererBuilder {
+    renderer: Renderer,
+}
+
+impl Default for RendererBuilder {
+    fn default() -> RendererBuilder {
+        RendererBuilder {
+            renderer: Renderer {
+                size: (80, 24),
+                margin: 0,
+                depth: ColorDepth::default(),
+                table_style: TableStyle::default(),
+                theme: Theme::default(),
+                graphics: Graphics::default(),
+                root: PathBuf::from("."),
+                plain: false,
+                links: Links::default(),
+                front_matter: FrontMatter::default(),
+                base_url: None,
+                task_summary: false,
+                highlighting: None,
+            },
+        }
+    }
+}
+
+impl RendererBuilder {
+    /// Columns text is wrapped to.
+    pub fn width(mut self, width: u16) -> RendererBuilder {
+        self.renderer.size.0 = width;
+        self
+    }
+
+    /// Columns every line is shifted right by, for centering the text on a
+    /// terminal wider than `width`.
+    pub fn margin(mut self, margin: u16) -> RendererBuilder {
+        self.renderer.margin = margin;
+        self
+    }
+
+    /// Lines images are fit into.
+    pub fn height(mut self, height: u16) -> RendererBuilder {
+        self.renderer.size.1 = height;
+        self
+    }
+
+    /// Colors the terminal supports.
+    pub fn color(mut self, depth: ColorDepth) -> RendererBuilder {
+        self.renderer.depth = depth;
+        self
+    }
+
+    pub fn table_style(mut self, table_style: TableStyle) -> RendererBuilder {
+        self.renderer.table_style = table_style;
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> RendererBuilder {
+        self.renderer.theme = theme;
+        self
+    }
+
+    pub fn graphics(mut self, graphics: Graphics) -> RendererBuilder {
+        self.renderer.graphics = graphics;
+        self
+    }
+
+    /// Directory relative image paths are resolved against.
+    pub fn root(mut self, root: PathBuf) -> RendererBuilder {
+        self.renderer.root = root;
+        self
+    }
+
+    /// Leaves out every escape sequence.
+    pub fn plain(mut self, plain: bool) -> RendererBuilder {
+        self.renderer.plain = plain;
+        self
+    }
+
+    pub fn links(mut self, links: Links) -> RendererBuilder {
+        self.renderer.links = links;
+        self
+    }
+
+    /// Whether front matter is left out, or shown as a table or title banner.
+    pub fn front_matter(mut self, front_matter: FrontMatter) -> RendererBuilder {
+        self.renderer.front_matter = front_matter;
+        self
+    }
+
+    /// URL relative links are resolved against.
+    pub fn base_url(mut self, base_url: Url) -> RendererBuilder {
+        self.renderer.base_url = Some(base_url);
+        self
+    }
+
+    /// Follows task lists with how many of their tasks are done.
+    pub fn task_summary(mut self, task_summary: bool) -> RendererBuilder {
+        self.renderer.task_summary = task_summary;
+        self
+    }
+
+    /// Syntaxes and themes for code blocks, instead of the bundled ones.
+    pub fn highlighting(mut self, syntax_set: SyntaxSet, theme_set: ThemeSet) -> RendererBuilder {
+        self.renderer.highlighting = Some((Arc::new(syntax_set), Arc::new(theme_set)));
+        self
+    }
+
+    pub fn build(self) -> Renderer {
+        self.renderer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendered_lines_know_their_width() {
+        let renderer = Renderer::builder()
+            .color(ColorDepth::Ansi256)
+            .links(Links::Osc8)
+            .build();
+        let lines = renderer
+            .render_lines("**日本語** and [a link](http://x.org/)\n")
+            .unwrap();
+        let line = lines.iter().find(|l| !l.text.is_empty()).unwrap();
+        assert!(line.text.contains('\x1b'));
+        assert_eq!(line.plain(), "日本語 and a link");
+        assert_eq!(line.width, 17);
+    }
+
+    #[test]
+    fn heading_titles_leave_out_markers() {
+        let renderer = Renderer::builder().plain(true).build();
+        let input = "# Using [links](http://x.org/) and `code`[^1]\n\n[^1]: A note.\n";
+        let outline = renderer.render(input, &mut Vec::new()).unwrap();
+        assert_eq!(outline.headings()[0].title, "Using links and code");
+    }
+
+    #[test]
+    fn footnotes_point_back_to_their_sections() {
+        let renderer = Renderer::builder()
+            .plain(true)
+            .table_style(TableStyle::Ascii)
+            .build();
+        let input = "Intro[^a].\n\n# Install\n\nRun[^a] it[^b].\n\n[^a]: One.\n\n[^b]: Two.\n";
+        let out = renderer.render_to_string(input).unwrap();
+        let notes = &out[out.find("Footnotes").unwrap()..];
+        assert_eq!(
+            notes,
+            "Footnotes\n[^1] One.\n     <- top, 1 Install\n[^2] Two.\n     <- 1 Install\n"
+        );
+    }
+
+    #[test]
+    fn rules_and_tables_stay_inside_quotes() {
+        let renderer = Renderer::builder()
+            .width(20)
+            .plain(true)
+            .table_style(TableStyle::Ascii)
+            .build();
+        let input = "> ---\n>\n> | a | b |\n> |---|---|\n> | 1 | 2 |\n";
+        let out = renderer.render_to_string(input).unwrap();
+        let lines: Vec<&str> = out.lines().filter(|l| !l.is_empty()).collect();
+        assert!(lines.contains(&" > -----------------"), "{}", out);
+        assert!(lines.contains(&" > | a | b |"), "{}", out);
+        for line in lines {
+            assert!(line.starts_with(" >"), "{:?}", line);
+            assert!(text::display_width(line) <= 20, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn headings_know_their_own_line() {
+        let renderer = Renderer::builder().plain(true).build();
+        let mut out = Vec::new();
+        let outline = renderer
+            .render("# A\n\ntext\n\n> quote\n\n## B\n", &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        let titles: Vec<&str> = outline.headings().iter().map(|h| lines[h.line]).collect();
+        assert_eq!(titles, ["# A", "## B"]);
+    }
+
+    #[test]
+    fn nested_lists_follow_their_item() {
+        let renderer = Renderer::builder()
+            .plain(true)
+            .table_style(TableStyle::Ascii)
+            .build();
+        let out = renderer
+            .render_to_string("1. one\n2. two\n   - inner\n3. three\n")
+            .unwrap();
+        assert_eq!(
+            out.trim_start_matches('\n'),
+            " 1. one\n 2. two\n     - inner\n 3. three\n"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::text::{display_width, wrap};
+use pulldown_cmark::Alignment;
 use std::{
     borrow::Cow,
     io::{Result, Write},
@@ -5,14 +7,14 @@ use std::{
 };
 pub trait TableFns<'a> {
     fn set_table_state(&mut self, state: TableState);
-    fn set_width(&mut self, w: usize);
     fn width(&self) -> usize;
-    fn table_state(&self) -> TableState;
-    fn inc_col(&mut self);
     fn inc_index(&mut self);
+    /// Starts an empty cell, text pushed after this goes into it.
+    fn start_cell(&mut self);
     fn set_index(&mut self, idx: usize);
     fn index(&self) -> usize;
     fn table(&self) -> &[Cow<'a, str>];
+    fn alignments(&self) -> &[Alignment];
 }
 
 pub trait Table<'a>: TableFns<'a> {
@@ -23,7 +25,6 @@ pub trait Table<'a>: TableFns<'a> {
     const H_INNER_VERTICAL: char;
     const H_OUTER_LEFT_VERTICAL: char;
     const H_OUTER_RIGHT_VERTICAL: char;
-    const INNER_HORIZONTAL: char;
     const INNER_VERTICAL: char;
     const OUTER_BOTTOM_HORIZONTAL: char;
     const OUTER_BOTTOM_INTERSECT: char;
@@ -33,37 +34,34 @@ pub trait Table<'a>: TableFns<'a> {
     const OUTER_TOP_INTERSECT: char;
     const OUTER_TOP_LEFT: char;
     const OUTER_TOP_RIGHT: char;
+    /// spaces between cell content and the borders on either side
+    const PADDING: usize = 1;
 
-    fn new(width: usize) -> Self;
+    fn new(width: usize, alignments: Vec<Alignment>) -> Self;
 
-    fn draw<W: Write>(&mut self, w: &mut W) -> Result<()> {
-        // let total_width: usize = self.table()[0..self.index() - 1]
-        //     .iter()
-        //     .map(|x| x.len())
-        //     .sum();
-        // if total_width > self.width() {
-        //     let avg = total_width / self.width();
-        // }
+    /// Draws the table, borders are preceded by the escapes in `border`.
+    fn draw<W: Write>(&mut self, w: &mut W, border: &str) -> Result<()> {
+        let cols = self.index();
+        if cols == 0 {
+            return Ok(());
+        }
+        let widths = col_widths(self.table(), cols, self.width(), Self::PADDING);
+        let aligns: Vec<Alignment> = (0..cols)
+            .map(|col| {
+                self.alignments()
+                    .get(col)
+                    .cloned()
+                    .unwrap_or(Alignment::None)
+            })
+            .collect();
 
         let char_row = |left: char, hor: char, intr: char, right: char, w: &mut W| -> Result<()> {
-            write!(w, "{}", left)?;
-            for col in 0..self.index() - 1 {
-                let width = self.table()[col].len();
-                write!(
-                    w,
-                    "{}{}",
-                    iter::repeat(hor).take(width).collect::<String>(),
-                    intr
-                )?;
-            }
-            let width = self.table()[self.index() - 1].len();
-            write!(
-                w,
-                "{}{}\n",
-                iter::repeat(hor).take(width).collect::<String>(),
-                right
-            )?;
-            Ok(())
+            let segments: Vec<String> = widths
+                .iter()
+                .map(|&width| iter::repeat_n(hor, width + 2 * Self::PADDING).collect())
+                .collect();
+            let row = format!("{}{}{}", left, segments.join(&intr.to_string()), right);
+            writeln!(w, "{}", paint(border, &row))
         };
 
         // top row
@@ -75,43 +73,46 @@ pub trait Table<'a>: TableFns<'a> {
             w,
         )?;
 
-        // header row
-        write!(w, "{}", Self::H_OUTER_LEFT_VERTICAL)?;
-        for col in 0..self.index() - 1 {
-            write!(w, "{}{}", self.table()[col], Self::H_INNER_VERTICAL)?;
-        }
-
-        write!(
-            w,
-            "{}{}\n",
-            self.table()[self.index() - 1],
-            Self::H_OUTER_RIGHT_VERTICAL
-        )?;
-
-        // bottom head
-        char_row(
-            Self::OUTER_BOTTOM_LEFT,
-            Self::OUTER_BOTTOM_HORIZONTAL,
-            Self::OUTER_BOTTOM_INTERSECT,
-            Self::OUTER_BOTTOM_RIGHT,
-            w,
-        )?;
-
-        // body rows
-        let pos = |row: usize, col: usize| row * self.index() + col;
-
-        for row in 1..(self.table().len() / self.index()) {
-            write!(w, "{}", Self::INNER_VERTICAL)?;
-            for col in 0..self.index() - 1 {
-                let idx = pos(row, col);
-                write!(w, "{}{}", self.table()[idx], Self::INNER_VERTICAL)?;
+        for (row, cells) in self.table().chunks(cols).enumerate() {
+            if row == 0 {
+                // header row
+                draw_row(
+                    w,
+                    cells,
+                    &widths,
+                    &aligns,
+                    Self::PADDING,
+                    border,
+                    (
+                        Self::H_OUTER_LEFT_VERTICAL,
+                        Self::H_INNER_VERTICAL,
+                        Self::H_OUTER_RIGHT_VERTICAL,
+                    ),
+                )?;
+                // bottom head
+                char_row(
+                    Self::OUTER_BOTTOM_LEFT,
+                    Self::OUTER_BOTTOM_HORIZONTAL,
+                    Self::OUTER_BOTTOM_INTERSECT,
+                    Self::OUTER_BOTTOM_RIGHT,
+                    w,
+                )?;
+            } else {
+                // body rows
+                draw_row(
+                    w,
+                    cells,
+                    &widths,
+                    &aligns,
+                    Self::PADDING,
+                    border,
+                    (
+                        Self::INNER_VERTICAL,
+                        Self::INNER_VERTICAL,
+                        Self::INNER_VERTICAL,
+                    ),
+                )?;
             }
-            write!(
-                w,
-                "{}{}\n",
-                self.table()[pos(row, self.index() - 1)],
-                Self::INNER_VERTICAL
-            )?;
         }
 
         // footer row
@@ -128,16 +129,114 @@ pub trait Table<'a>: TableFns<'a> {
     fn push(&mut self, item: Cow<'a, str>);
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum TableState {
-    Head,
-    Body,
+/// Content width of each column, fitting the table into `width` columns
+/// (borders and cell padding included) where possible.
+///
+/// Every column gets at least its longest word, the remaining space is shared
+/// out in proportion to how much each column would need to fit unwrapped. If
+/// even the longest words don't fit, columns are shrunk in proportion to their
+/// content and `wrap` splits words.
+fn col_widths(cells: &[Cow<'_, str>], cols: usize, width: usize, padding: usize) -> Vec<usize> {
+    let mut min = vec![1; cols];
+    let mut max = vec![1; cols];
+    for (i, cell) in cells.iter().enumerate() {
+        let col = i % cols;
+        let cell = cell.trim();
+        let longest_word = cell.split(' ').map(display_width).max().unwrap_or(0);
+        min[col] = min[col].max(longest_word);
+        max[col] = max[col].max(display_width(cell));
+    }
+
+    // one border per column plus the closing one, padding either side
+    let avail = width.saturating_sub((1 + 2 * padding) * cols + 1);
+    let total_max: usize = max.iter().sum();
+    let total_min: usize = min.iter().sum();
+    if total_max <= avail {
+        max
+    } else if total_min <= avail {
+        let extra = avail - total_min;
+        let wanted = total_max - total_min;
+        let mut widths: Vec<usize> = min
+            .iter()
+            .zip(&max)
+            .map(|(&lo, &hi)| lo + (hi - lo) * extra / wanted)
+            .collect();
+        // hand out what integer division left over
+        let mut left = avail - widths.iter().sum::<usize>();
+        for (width, &hi) in widths.iter_mut().zip(&max) {
+            if left == 0 {
+                break;
+            }
+            if *width < hi {
+                *width += 1;
+                left -= 1;
+            }
+        }
+        widths
+    } else {
+        max.iter()
+            .map(|&hi| (hi * avail / total_max).max(1))
+            .collect()
+    }
+}
+
+fn paint(style: &str, s: &str) -> String {
+    if style.is_empty() {
+        s.to_string()
+    } else {
+        format!("{}{}\x1b[m", style, s)
+    }
 }
 
-impl Default for TableState {
-    fn default() -> Self {
-        TableState::Head
+/// Writes one table row, wrapping each cell to its column width and aligning
+/// it within the column. The row is as many lines tall as its tallest cell.
+fn draw_row<W: Write>(
+    w: &mut W,
+    cells: &[Cow<'_, str>],
+    widths: &[usize],
+    aligns: &[Alignment],
+    padding: usize,
+    border: &str,
+    (left, inner, right): (char, char, char),
+) -> Result<()> {
+    let wrapped: Vec<Vec<String>> = widths
+        .iter()
+        .enumerate()
+        .map(|(col, &width)| wrap(cells.get(col).map_or("", |c| c.trim()), width))
+        .collect();
+    let height = wrapped.iter().map(Vec::len).max().unwrap_or(1);
+
+    for line in 0..height {
+        write!(w, "{}", paint(border, &left.to_string()))?;
+        for (col, (lines, &width)) in wrapped.iter().zip(widths).enumerate() {
+            if col > 0 {
+                write!(w, "{}", paint(border, &inner.to_string()))?;
+            }
+            let text = lines.get(line).map_or("", String::as_str);
+            let fill = width.saturating_sub(display_width(text));
+            let (before, after) = match aligns[col] {
+                Alignment::Right => (fill, 0),
+                Alignment::Center => (fill / 2, fill - fill / 2),
+                Alignment::Left | Alignment::None => (0, fill),
+            };
+            write!(
+                w,
+                "{}{}{}",
+                " ".repeat(padding + before),
+                text,
+                " ".repeat(after + padding)
+            )?;
+        }
+        writeln!(w, "{}", paint(border, &right.to_string()))?;
     }
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub enum TableState {
+    #[default]
+    Head,
+    Body,
 }
 
 macro_rules! impl_table {
@@ -146,21 +245,21 @@ macro_rules! impl_table {
             fn table(&self) -> &[Cow<'a, str>] {
                 self.table.as_slice()
             }
-            fn set_table_state(&mut self, state: TableState) {
-                self.table_state = state;
-            }
 
-            fn table_state(&self) -> TableState {
-                self.table_state
+            fn alignments(&self) -> &[Alignment] {
+                self.table_alignments.as_slice()
             }
-
-            fn inc_col(&mut self) {
-                self.col_count += 1;
+            fn set_table_state(&mut self, state: TableState) {
+                self.table_state = state;
             }
 
             fn inc_index(&mut self) {
                 self.table_cell_index += 1;
-                self.cur += 1;
+            }
+
+            fn start_cell(&mut self) {
+                self.table.push(Cow::Borrowed(""));
+                self.cur = self.table.len() - 1;
             }
 
             fn index(&self) -> usize {
@@ -171,10 +270,6 @@ macro_rules! impl_table {
                 self.table_cell_index = idx;
             }
 
-            fn set_width(&mut self, w: usize) {
-                self.width = w;
-            }
-
             fn width(&self) -> usize {
                 self.width
             }
@@ -187,8 +282,7 @@ pub struct AsciiTable<'a> {
     table: Vec<Cow<'a, str>>,
     cur: usize,
     table_state: TableState,
-    col_count: usize,
-    // table_alignments: Vec<Alignment>,
+    table_alignments: Vec<Alignment>,
     table_cell_index: usize,
     width: usize,
 }
@@ -203,7 +297,6 @@ impl<'a> Table<'a> for AsciiTable<'a> {
     const H_INNER_VERTICAL: char = '|';
     const H_OUTER_LEFT_VERTICAL: char = '|';
     const H_OUTER_RIGHT_VERTICAL: char = '|';
-    const INNER_HORIZONTAL: char = '-';
     const INNER_VERTICAL: char = '|';
     const OUTER_BOTTOM_HORIZONTAL: char = '-';
     const OUTER_BOTTOM_INTERSECT: char = '+';
@@ -214,19 +307,17 @@ impl<'a> Table<'a> for AsciiTable<'a> {
     const OUTER_TOP_LEFT: char = '+';
     const OUTER_TOP_RIGHT: char = '+';
 
-    fn new(width: usize) -> Self {
+    fn new(width: usize, table_alignments: Vec<Alignment>) -> Self {
         AsciiTable {
             width,
+            table_alignments,
             ..AsciiTable::default()
         }
     }
 
     fn push(&mut self, item: Cow<'a, str>) {
-        let len = self.table.len();
-        if len == self.cur {
-            self.table.push(item);
-        } else {
-            self.table[self.cur].to_mut().push_str(&item);
+        if let Some(cell) = self.table.get_mut(self.cur) {
+            cell.to_mut().push_str(&item);
         }
     }
 }
@@ -237,7 +328,7 @@ pub struct UnicodeTable<'a> {
     table: Vec<Cow<'a, str>>,
     cur: usize,
     table_state: TableState,
-    col_count: usize,
+    table_alignments: Vec<Alignment>,
     table_cell_index: usize,
     width: usize,
 }
@@ -252,7 +343,6 @@ impl<'a> Table<'a> for UnicodeTable<'a> {
     const H_INNER_VERTICAL: char = '│';
     const H_OUTER_LEFT_VERTICAL: char = '│';
     const H_OUTER_RIGHT_VERTICAL: char = '│';
-    const INNER_HORIZONTAL: char = '─';
     const INNER_VERTICAL: char = '│';
     const OUTER_BOTTOM_HORIZONTAL: char = '─';
     const OUTER_BOTTOM_INTERSECT: char = '┼';
@@ -263,19 +353,46 @@ impl<'a> Table<'a> for UnicodeTable<'a> {
     const OUTER_TOP_LEFT: char = '┌';
     const OUTER_TOP_RIGHT: char = '┐';
 
-    fn new(width: usize) -> Self {
+    fn new(width: usize, table_alignments: Vec<Alignment>) -> Self {
         UnicodeTable {
             width,
+            table_alignments,
             ..UnicodeTable::default()
         }
     }
 
     fn push(&mut self, item: Cow<'a, str>) {
-        let len = self.table.len();
-        if len == self.cur {
-            self.table.push(item);
-        } else {
-            self.table[self.cur].to_mut().push_str(&item);
+        if let Some(cell) = self.table.get_mut(self.cur) {
+            cell.to_mut().push_str(&item);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::col_widths;
+    use crate::{Renderer, TableStyle};
+    use std::borrow::Cow;
+
+    #[test]
+    fn empty_cells_keep_their_column() {
+        let renderer = Renderer::builder()
+            .table_style(TableStyle::Ascii)
+            .plain(true)
+            .build();
+        let out = renderer
+            .render_to_string("| a | b | c |\n|---|---|---|\n| | x | |\n| 1 | | 3 |\n")
+            .unwrap();
+        let rows: Vec<&str> = out.lines().filter(|l| l.starts_with('|')).collect();
+        assert_eq!(rows, ["| a | b | c |", "|   | x |   |", "| 1 |   | 3 |"]);
+    }
+
+    #[test]
+    fn empty_cells_are_measured_in_their_column() {
+        let cells: Vec<Cow<str>> = ["", "wide column", "", "x", "", "y"]
+            .iter()
+            .map(|&c| Cow::Borrowed(c))
+            .collect();
+        assert_eq!(col_widths(&cells, 3, 80, 1), [1, 11, 1]);
+    }
+}
@@ -1,75 +1,135 @@
-pub use crate::table::{AsciiTable, Table, TableState, UnicodeTable};
-use pulldown_cmark::{Alignment, Event, Tag};
+use crate::table::{Table, TableState};
+use crate::{
+    color::{indexed, reset, rgb, ColorDepth},
+    glyphs::Glyphs,
+    img::{self, Graphics},
+    links::{Links, Registry},
+    outline::Outline,
+    text::{display_width, link_start, strip_escapes, wrap, Strip, LINK_END},
+    theme::Theme,
+};
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, Tag, TagEnd};
 use std::{
     borrow::Cow,
     collections::HashMap,
     fmt::Debug,
     io::{Result, Write},
+    path::PathBuf,
+    sync::Arc,
 };
 use syntect::{
     easy::HighlightLines,
-    highlighting::{FontStyle, Style, ThemeSet},
+    highlighting::{Color as SynColor, FontStyle, Style, ThemeSet},
     parsing::SyntaxSet,
 };
-use termion::{color, style};
+use termion::style;
+use url::Url;
 
 lazy_static! {
-    static ref RESET_COLOR: String = format!("{}", color::Fg(color::Reset));
     static ref RESET_STYLE: String = format!("{}", style::Reset);
+    // loading these takes a while, terminals share one copy
+    static ref SYNTAX_SET: Arc<SyntaxSet> = Arc::new(SyntaxSet::load_defaults_newlines());
+    static ref THEME_SET: Arc<ThemeSet> = Arc::new(ThemeSet::load_defaults());
 }
 
-pub type TermAscii<'a> = Terminal<'a, AsciiTable<'a>>;
-pub type TermUnicode<'a> = Terminal<'a, UnicodeTable<'a>>;
-
 pub trait MDParser<'a, I, W>
 where
     I: Iterator<Item = Event<'a>>,
     W: Write,
 {
     fn parse(&mut self, iter: I, w: &mut W) -> Result<()>;
+    /// Headings seen by `parse`.
+    fn outline(&self) -> &Outline;
+}
+
+/// Writer counting the lines written through it.
+struct LineCount<W> {
+    inner: W,
+    lines: usize,
+}
+
+impl<W: Write> Write for LineCount<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.lines += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
 }
 
 pub struct Terminal<'a, T> {
-    indent_lvl: usize,
+    /// inline text of the block being rendered, wrapped when it's flushed
+    text: String,
+    /// written before every wrapped line, one entry per open container
+    gutter: Vec<String>,
+    /// replaces the innermost `gutter` entry on the next line, i.e. a list
+    /// marker
+    lead: Option<String>,
     term_size: (u16, u16),
-    links: Vec<(Cow<'a, str>, Cow<'a, str>)>,
-    truecolor: bool,
+    glyphs: Glyphs,
+    links: Registry,
+    link_mode: Links,
+    /// number of the link being read
+    link: Option<usize>,
+    /// image being read, its alt text is collected until the tag ends
+    image: Option<(Cow<'a, str>, String)>,
+    /// heading being read: level, title so far and the line it's on
+    heading: Option<(i32, String, usize)>,
+    outline: Outline,
+    /// directory relative image paths are resolved against
+    root: PathBuf,
+    graphics: Graphics,
+    /// pixel size of a terminal cell, for the graphics protocols
+    cell_size: (u32, u32),
+    depth: ColorDepth,
+    /// no escape sequences at all, for output that isn't a terminal
+    plain: bool,
+    /// count the tasks of task lists under them
+    task_summary: bool,
+    theme: Theme,
+    /// escapes of the open inline styles, innermost last
+    styles: Vec<String>,
+    /// `gutter` entries of the open blockquotes
+    quotes: Vec<usize>,
     dontskip: bool,
-    syntax_set: SyntaxSet,
-    theme_set: ThemeSet,
-    state: State<T>,
+    syntax_set: Arc<SyntaxSet>,
+    theme_set: Arc<ThemeSet>,
+    /// blocks being rendered, innermost last, on top of `State::Nil`
+    states: Vec<State<T>>,
 }
 
+#[derive(Default)]
 enum State<T> {
     Code {
         code: String,
         lang: Option<String>,
     },
     Table {
-        table_alignments: Vec<Alignment>,
         table: T,
     },
-    Li,
+    /// `tasks` counts the task items, done and in total
+    Li {
+        tasks: (usize, usize),
+    },
+    /// `next` is the number of the next item
     Ol {
-        items: usize,
+        next: usize,
+        tasks: (usize, usize),
     },
+    #[default]
     Nil,
 }
 
-impl<T> Default for State<T> {
-    fn default() -> Self {
-        State::Nil
-    }
-}
-
 impl<'a, T> State<T>
 where
     T: Table<'a>,
 {
     fn table(table_alignments: Vec<Alignment>, width: usize) -> State<T> {
         State::Table {
-            table: T::new(width),
-            table_alignments,
+            table: T::new(width, table_alignments),
         }
     }
     fn set_table_state(&mut self, table_state: TableState) {
@@ -77,12 +137,17 @@ where
             table.set_table_state(table_state);
         }
     }
-    fn table_draw<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+    fn table_draw<W: Write>(&mut self, buf: &mut W, border: &str) -> Result<()> {
         if let State::Table { ref mut table, .. } = *self {
-            table.draw(buf)?;
+            table.draw(buf, border)?;
         }
         Ok(())
     }
+    fn table_start_cell(&mut self) {
+        if let State::Table { ref mut table, .. } = *self {
+            table.start_cell();
+        }
+    }
     fn table_inc_index(&mut self) {
         if let State::Table { ref mut table, .. } = *self {
             table.inc_index();
@@ -99,33 +164,47 @@ where
             lang,
         }
     }
-    fn write_buf<W: Write>(&mut self, buf: &mut W, text: Cow<'a, str>) -> Result<()> {
+    fn write_buf(&mut self, buf: &mut String, text: Cow<'a, str>) {
         match *self {
             State::Code { ref mut code, .. } => code.push_str(&text),
             State::Table { ref mut table, .. } => table.push(text),
-            _ => write!(buf, "{}", text)?,
+            _ => buf.push_str(&text),
         }
-        Ok(())
     }
 
     fn li() -> State<T> {
-        State::Li
+        State::Li { tasks: (0, 0) }
     }
     fn ol(start: usize) -> State<T> {
-        State::Ol { items: start }
+        State::Ol {
+            next: start,
+            tasks: (0, 0),
+        }
+    }
+    fn is_list(&self) -> bool {
+        matches!(*self, State::Li { .. } | State::Ol { .. })
     }
-    fn inc_li<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+    /// Marker of the next item, `bullet` unless the list is numbered.
+    fn inc_li(&mut self, style: &str, bullet: char) -> String {
         match *self {
-            State::Ol { ref mut items } => {
-                *items += 1;
-                write!(buf, " {}", &(items.to_string() + ". "))?;
-            }
-            _ => {
-                write!(buf, "{} * ", color::Fg(color::Red))?;
-                write!(buf, "{}", *RESET_COLOR)?;
+            State::Ol { ref mut next, .. } => {
+                *next += 1;
+                format!("{} {}. {}", style, *next - 1, *RESET_STYLE)
             }
-        };
-        Ok(())
+            _ => format!("{} {} {}", style, bullet, *RESET_STYLE),
+        }
+    }
+    fn count_task(&mut self, checked: bool) {
+        if let State::Li { ref mut tasks } | State::Ol { ref mut tasks, .. } = *self {
+            tasks.0 += usize::from(checked);
+            tasks.1 += 1;
+        }
+    }
+    fn tasks(&self) -> (usize, usize) {
+        match *self {
+            State::Li { tasks } | State::Ol { tasks, .. } => tasks,
+            _ => (0, 0),
+        }
     }
 }
 
@@ -136,13 +215,29 @@ where
     fn default() -> Self {
         Terminal {
             dontskip: false,
-            indent_lvl: 0,
+            text: String::new(),
+            gutter: Vec::new(),
+            lead: None,
             term_size: (100, 100),
-            links: Vec::new(),
-            truecolor: false,
-            syntax_set: SyntaxSet::load_defaults_newlines(),
-            theme_set: ThemeSet::load_defaults(),
-            state: State::Nil,
+            glyphs: Glyphs::default(),
+            links: Registry::default(),
+            link: None,
+            image: None,
+            heading: None,
+            outline: Outline::default(),
+            root: PathBuf::from("."),
+            graphics: Graphics::default(),
+            cell_size: (8, 16),
+            depth: ColorDepth::default(),
+            plain: false,
+            link_mode: Links::default(),
+            task_summary: false,
+            theme: Theme::default(),
+            styles: Vec::new(),
+            quotes: Vec::new(),
+            syntax_set: SYNTAX_SET.clone(),
+            theme_set: THEME_SET.clone(),
+            states: vec![State::Nil],
         }
     }
 }
@@ -154,267 +249,681 @@ where
     W: Write,
 {
     fn parse(&mut self, iter: I, w: &mut W) -> Result<()> {
-        let mut numbers = HashMap::new();
+        // footnotes are numbered in the order they're first referenced
+        let mut numbers: HashMap<Cow<'a, str>, usize> = HashMap::new();
+        // definitions are rendered on the side and written at the end
+        let mut note: Option<(usize, Vec<u8>)> = None;
+        let mut notes = Vec::new();
+        // sections each footnote is referenced from, listed after it
+        let mut refs: HashMap<usize, Vec<String>> = HashMap::new();
+        let w = &mut LineCount {
+            inner: Strip::new(w, self.plain),
+            lines: 0,
+        };
 
         for event in iter {
+            let mut out: &mut dyn Write = match note {
+                Some((_, ref mut buf)) => buf,
+                None => &mut *w,
+            };
             match event {
+                Event::Start(Tag::FootnoteDefinition(name)) => {
+                    self.flush(&mut out)?;
+                    let len = numbers.len() + 1;
+                    let number = *numbers.entry(name.into()).or_insert(len);
+                    let label = format!("{} ", self.glyphs.footnote_mark(number));
+                    self.gutter.push(" ".repeat(display_width(&label)));
+                    self.lead = Some(label);
+                    self.dontskip = true;
+                    note = Some((number, Vec::new()));
+                }
+                Event::End(TagEnd::FootnoteDefinition) => {
+                    self.flush(&mut out)?;
+                    self.flush_lead(&mut out)?;
+                    self.gutter.pop();
+                    notes.extend(note.take());
+                }
                 Event::Start(tag) => {
-                    self.increment();
-                    self.start_tag(tag, w, &mut numbers)?;
+                    let level = match tag {
+                        Tag::Heading { level, .. } => Some(level as i32),
+                        _ => None,
+                    };
+                    self.start_tag(tag, &mut out)?;
+                    // after the blank line start_tag writes above the heading
+                    if let Some(level) = level {
+                        self.heading = Some((level, String::new(), w.lines));
+                    }
                 }
                 Event::End(tag) => {
-                    self.decrement();
-                    self.end_tag(&tag, w)?;
+                    self.end_tag(&tag, &mut out)?;
+                }
+                Event::InlineHtml(html) | Event::Html(html) => self.write_inline(html),
+                Event::Text(text) | Event::InlineMath(text) | Event::DisplayMath(text) => {
+                    self.add_to_title(&text);
+                    self.write_inline(text)
+                }
+                Event::Code(code) => {
+                    self.add_to_title(&code);
+                    self.push_style(self.theme.code.start(self.depth));
+                    self.write_inline(format!("`{}`", code));
+                    self.pop_style();
                 }
-                Event::InlineHtml(html) | Event::Html(html) => self.state.write_buf(w, html)?,
-                Event::Text(text) => self.state.write_buf(w, text)?,
                 Event::SoftBreak => self.soft_break(),
                 Event::HardBreak => self.hard_break(),
-                Event::FootnoteReference(name) => self.state.write_buf(w, name)?,
+                Event::Rule => self.write_rule(&mut out)?,
+                Event::FootnoteReference(name) => {
+                    let len = numbers.len() + 1;
+                    let number = *numbers.entry(name.into()).or_insert(len);
+                    self.write_inline(self.glyphs.footnote_mark(number));
+                    let section = match self.outline.headings().last() {
+                        Some(heading) => format!("{} {}", heading.number, heading.title),
+                        None => "top".to_string(),
+                    };
+                    let sections = refs.entry(number).or_default();
+                    if !sections.contains(&section) {
+                        sections.push(section);
+                    }
+                }
+                Event::TaskListMarker(checked) => self.task(checked),
             }
         }
+        self.flush(w)?;
 
-        // write links as footnotes
-        for (i, &(ref dest, ref title)) in self.links.iter().enumerate() {
-            let i = i + 1;
-            if !title.is_empty() {
-                write!(w, "[{}] {}: {}\n", i, title, dest).unwrap();
-            } else {
-                write!(w, "[{}] {}\n", i, dest).unwrap();
+        if !notes.is_empty() {
+            notes.sort_by_key(|&(number, _)| number);
+            writeln!(
+                w,
+                "\n{}Footnotes{}",
+                self.theme.strong.start(self.depth),
+                *RESET_STYLE
+            )?;
+            for (number, text) in notes {
+                w.write_all(&text)?;
+                if let Some(sections) = refs.get(&number) {
+                    let indent = display_width(&self.glyphs.footnote_mark(number)) + 1;
+                    writeln!(
+                        w,
+                        "{}{}{} {}{}",
+                        " ".repeat(indent),
+                        self.theme.quote.start(self.depth),
+                        self.glyphs.back,
+                        sections.join(", "),
+                        *RESET_STYLE
+                    )?;
+                }
             }
         }
+
+        if self.footnotes() {
+            self.write_links(w)?;
+        }
         Ok(())
     }
+
+    fn outline(&self) -> &Outline {
+        &self.outline
+    }
 }
 
 impl<'a, T> Terminal<'a, T>
 where
     T: Table<'a> + Debug,
 {
-    pub fn new(term_size: (u16, u16), truecolor: bool) -> Terminal<'a, T> {
+    pub fn new(term_size: (u16, u16), depth: ColorDepth) -> Terminal<'a, T> {
         Terminal {
             term_size,
-            truecolor,
+            depth,
             ..Terminal::default()
         }
     }
 
-    fn increment(&mut self) {
-        self.indent_lvl += 1;
+    /// Sets the characters lists, blockquotes and footnotes are marked with.
+    pub fn with_glyphs(mut self, glyphs: Glyphs) -> Terminal<'a, T> {
+        self.glyphs = glyphs;
+        self
     }
 
-    fn decrement(&mut self) {
-        self.indent_lvl -= 1;
+    /// Sets the directory that relative image paths are resolved against,
+    /// usually the one containing the document.
+    pub fn with_root(mut self, root: PathBuf) -> Terminal<'a, T> {
+        self.root = root;
+        self
+    }
+
+    /// Writes the document without any escape sequences, keeping only its
+    /// layout. Images are left as placeholders.
+    pub fn with_plain(mut self, plain: bool) -> Terminal<'a, T> {
+        self.plain = plain;
+        self
+    }
+
+    /// Resolves relative link destinations against `base`, if given.
+    pub fn with_base_url(mut self, base: Option<Url>) -> Terminal<'a, T> {
+        self.links = Registry::new(base);
+        self
+    }
+
+    /// Shows links as `link_mode` says.
+    pub fn with_links(mut self, link_mode: Links) -> Terminal<'a, T> {
+        self.link_mode = link_mode;
+        self
+    }
+
+    /// Follows task lists with how many of their tasks are done.
+    pub fn with_task_summary(mut self, task_summary: bool) -> Terminal<'a, T> {
+        self.task_summary = task_summary;
+        self
+    }
+
+    /// Colors the document with `theme`.
+    pub fn with_theme(mut self, theme: Theme) -> Terminal<'a, T> {
+        self.theme = theme;
+        self
+    }
+
+    /// Highlights code blocks with these syntaxes and themes instead of the
+    /// bundled ones.
+    pub fn with_highlighting(
+        mut self,
+        syntax_set: Arc<SyntaxSet>,
+        theme_set: Arc<ThemeSet>,
+    ) -> Terminal<'a, T> {
+        self.syntax_set = syntax_set;
+        self.theme_set = theme_set;
+        self
+    }
+
+    /// Draws images with `graphics` instead of unicode blocks.
+    pub fn with_graphics(mut self, graphics: Graphics) -> Terminal<'a, T> {
+        self.graphics = graphics;
+        self.cell_size = img::cell_size();
+        self
     }
 
     fn width(&self) -> usize {
         self.term_size.0 as usize
     }
 
-    fn start_tag<W: Write>(
-        &mut self,
-        tag: Tag<'a>,
-        buf: &mut W,
-        numbers: &mut HashMap<Cow<'a, str>, usize>,
-    ) -> Result<()> {
+    // only the text of a heading makes its title, not the link and footnote
+    // markers written after it
+    fn add_to_title(&mut self, text: &str) {
+        if let Some((_, ref mut title, _)) = self.heading {
+            title.push_str(text);
+        }
+    }
+
+    fn write_inline<S: Into<Cow<'a, str>>>(&mut self, text: S) {
+        let text = text.into();
+        match self.image {
+            Some((_, ref mut alt)) => alt.push_str(&text),
+            None => {
+                if let Some(state) = self.states.last_mut() {
+                    state.write_buf(&mut self.text, text);
+                }
+            }
+        }
+    }
+
+    /// The innermost block being rendered.
+    fn state(&mut self) -> &mut State<T> {
+        self.states.last_mut().expect("State::Nil is never popped")
+    }
+
+    /// Opens an inline style, it lasts until the matching `pop_style`.
+    fn push_style(&mut self, style: String) {
+        self.write_inline(style.clone());
+        self.styles.push(style);
+    }
+
+    /// Closes the innermost inline style, restoring the ones around it.
+    fn pop_style(&mut self) {
+        self.styles.pop();
+        let mut restore = RESET_STYLE.to_string();
+        if !self.quotes.is_empty() {
+            restore.push_str(&self.theme.quote.start(self.depth));
+        }
+        restore.push_str(&self.styles.concat());
+        self.write_inline(restore);
+    }
+
+    /// Wraps the buffered inline text to the terminal width and writes it,
+    /// prefixing every line with the gutter of the enclosing containers.
+    fn flush<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+        // escapes alone are kept for the next block
+        if display_width(&self.text) == 0 && !self.text.contains('\n') {
+            return Ok(());
+        }
+        let text = std::mem::take(&mut self.text);
+        let width = self.inner_width();
+        let rest = self.gutter.concat();
+        let first = self.first_prefix();
+        for (i, line) in wrap(text.trim_start_matches(' '), width).iter().enumerate() {
+            writeln!(buf, "{}{}", if i == 0 { &first } else { &rest }, line)?;
+        }
+        Ok(())
+    }
+
+    /// Columns left for content inside the gutter.
+    fn inner_width(&self) -> usize {
+        self.width()
+            .saturating_sub(display_width(&self.gutter.concat()))
+    }
+
+    /// Ends the line, or writes an empty one, with the gutter of the
+    /// enclosing containers so blockquote bars carry on through it.
+    fn fresh_line<W: Write>(&self, buf: &mut W) -> Result<()> {
+        writeln!(buf, "{}", self.gutter.concat().trim_end())
+    }
+
+    /// Writes a pending `lead` on a line of its own, for containers that end
+    /// or nest before any text was flushed.
+    fn flush_lead<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+        if self.lead.is_some() {
+            let prefix = self.first_prefix();
+            writeln!(buf, "{}", prefix)?;
+        }
+        Ok(())
+    }
+
+    /// Ends the text before a list. Lists nested in an item follow it
+    /// directly, others get a blank line above them.
+    fn list_start<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+        self.flush(buf)?;
+        self.flush_lead(buf)?;
+        if !self.states.last().is_some_and(State::is_list) {
+            self.fresh_line(buf)?;
+        }
+        Ok(())
+    }
+
+    fn first_prefix(&mut self) -> String {
+        match self.lead.take() {
+            Some(lead) => {
+                let outer = self.gutter.len().saturating_sub(1);
+                self.gutter[..outer].concat() + &lead
+            }
+            None => self.gutter.concat(),
+        }
+    }
+
+    fn start_tag<W: Write>(&mut self, tag: Tag<'a>, buf: &mut W) -> Result<()> {
         match tag {
             Tag::Paragraph => {
+                self.flush(buf)?;
                 if !self.dontskip {
-                    fresh_line(buf)?;
+                    self.fresh_line(buf)?;
                 }
                 self.dontskip = false;
             }
-            Tag::Rule => {
-                fresh_line(buf)?;
-                write!(buf, "{}", &"-".repeat(self.width()))?;
-            }
-            Tag::Header(level) => {
-                fresh_line(buf)?;
-                write!(
-                    buf,
-                    "{}{} {} ",
-                    color::Fg(color::Yellow),
-                    "#".repeat(level as usize),
-                    color::Fg(color::Red)
-                )?;
+            Tag::Heading { level, .. } => {
+                let level = level as i32;
+                self.flush(buf)?;
+                self.fresh_line(buf)?;
+                self.push_style(self.theme.heading(level).start(self.depth));
+                self.write_inline(format!("{} ", "#".repeat(level as usize)));
             }
             Tag::Table(alignments) => {
-                fresh_line(buf)?;
-                self.state = State::table(alignments, self.width());
+                self.flush(buf)?;
+                self.fresh_line(buf)?;
+                self.states
+                    .push(State::table(alignments, self.inner_width()));
             }
             Tag::TableHead => {
-                self.state.set_table_state(TableState::Head);
+                self.state().set_table_state(TableState::Head);
             }
             Tag::TableRow => {
-                self.state.set_table_index(0);
-            }
-            Tag::TableCell => {
-                // write!(
-                //     buf,
-                //     "{}",
-                //     match self.table_alignments.get(self.table.index()) {
-                //         Some(&Alignment::Left) => " align=\"left\"",
-                //         Some(&Alignment::Center) => " align=\"center\"",
-                //         Some(&Alignment::Right) => " align=\"right\"",
-                //         _ => "",
-                //     }
-                // )?;
-            }
-            Tag::BlockQuote => {
-                fresh_line(buf)?;
-                write!(
-                    buf,
-                    "{}{}> ",
-                    color::Fg(color::Green),
-                    "   ".repeat(self.indent_lvl)
-                )?;
+                self.state().set_table_index(0);
+            }
+            Tag::TableCell => self.state().table_start_cell(),
+            Tag::BlockQuote(_) => {
+                self.flush(buf)?;
+                self.fresh_line(buf)?;
+                // quotes directly inside another sit right next to its bar
+                let nested = self
+                    .quotes
+                    .last()
+                    .is_some_and(|&q| q + 1 == self.gutter.len());
+                self.quotes.push(self.gutter.len());
+                self.gutter.push(format!(
+                    "{}{}{} ",
+                    self.theme.quote.start(self.depth),
+                    if nested { "" } else { " " },
+                    self.glyphs.quote
+                ));
                 self.dontskip = true;
             }
-            Tag::CodeBlock(info) => {
-                fresh_line(buf)?;
-                self.state = State::code(info.split(' ').next().map(String::from));
-            }
-            Tag::List(Some(1)) => {
-                fresh_line(buf)?;
-                // <ol>
-                self.state = State::ol(0);
+            Tag::CodeBlock(kind) => {
+                self.flush(buf)?;
+                self.fresh_line(buf)?;
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split(' ')
+                        .next()
+                        .filter(|lang| !lang.is_empty())
+                        .map(String::from),
+                    CodeBlockKind::Indented => None,
+                };
+                self.states.push(State::code(lang));
             }
             Tag::List(Some(start)) => {
-                fresh_line(buf)?;
+                self.list_start(buf)?;
                 // <ol start=start>
-                self.state = State::ol(start);
+                self.states.push(State::ol(start as usize));
             }
             Tag::List(None) => {
                 // UL
-                fresh_line(buf)?;
-                self.state = State::li();
+                self.list_start(buf)?;
+                self.states.push(State::li());
             }
             Tag::Item => {
-                fresh_line(buf)?;
-                self.state.inc_li(buf)?;
+                self.flush(buf)?;
+                let level = self.states.iter().filter(|s| s.is_list()).count();
+                let bullet = self.glyphs.bullet(level);
+                let style = self.theme.list_marker.start(self.depth);
+                let marker = self.state().inc_li(&style, bullet);
+                self.gutter.push(" ".repeat(display_width(&marker)));
+                self.lead = Some(marker);
             }
             Tag::Emphasis => {
-                write!(buf, "{}", style::Italic)?;
+                self.push_style(self.theme.emphasis.start(self.depth));
             }
             Tag::Strong => {
-                write!(buf, "{}", style::Bold)?;
-            }
-            Tag::Code => {
-                write!(buf, "`{}", style::Italic)?;
-            }
-            Tag::Link(dest, title) => {
-                write!(buf, "{}", style::Underline)?;
-                self.links.push((dest, title));
-            }
-            Tag::Image(dest, title) => {
-                write!(buf, "<img src=\"{}", &dest)?;
-                write!(buf, "\" alt=\"")?;
-                //self.raw_text(numbers);
-                if !title.is_empty() {
-                    write!(buf, "\" title=\"{}", &title)?;
+                self.push_style(self.theme.strong.start(self.depth));
+            }
+            // crossed out text needs escapes, without them it's marked up
+            Tag::Strikethrough if self.depth == ColorDepth::NoColor => self.write_inline("~"),
+            Tag::Strikethrough => {
+                self.push_style(self.theme.strikethrough.start(self.depth));
+            }
+            Tag::Link {
+                dest_url, title, ..
+            } => {
+                let number = self.links.add(&dest_url, &title);
+                self.link = Some(number);
+                self.push_style(self.theme.link.start(self.depth));
+                if self.osc8() {
+                    self.write_inline(link_start(self.links.dest(number)));
                 }
-                write!(buf, "\" />")?;
             }
-            Tag::FootnoteDefinition(name) => {
-                fresh_line(buf)?;
-                let len = numbers.len() + 1;
-
-                // buf.push_str("<div class=\"footnote-definition\" id=\"");
-                // escape_html(buf, &*name, false);
-                // buf.push_str("\"><sup class=\"footnote-definition-label\">");
-
-                let number = numbers.entry(name).or_insert(len);
-
-                write!(buf, "[^{}] ", number.to_string())?;
-                self.dontskip = true;
+            Tag::Image { dest_url, .. } => {
+                self.image = Some((dest_url.into(), String::new()));
             }
+            _ => {}
         }
         Ok(())
     }
 
-    fn end_tag<W: Write>(&mut self, tag: &Tag<'a>, buf: &mut W) -> Result<()> {
+    fn end_tag<W: Write>(&mut self, tag: &TagEnd, buf: &mut W) -> Result<()> {
         match tag {
-            Tag::Paragraph => fresh_line(buf)?,
-            Tag::Rule => (),
-            Tag::Header(_) => {
-                write!(buf, "{}\n", *RESET_COLOR)?;
+            TagEnd::Paragraph => self.flush(buf)?,
+            TagEnd::Heading(_) => {
+                if let Some((level, title, line)) = self.heading.take() {
+                    let title = strip_escapes(&title).trim().to_string();
+                    self.outline.push(level, title, line);
+                }
+                self.pop_style();
+                self.flush(buf)?;
             }
-            Tag::Table(_) => {
+            TagEnd::Table => {
                 // self.in_table = false;
-                self.state.table_draw(buf)?;
-                self.state = State::default();
+                // drawn on the side so every line goes inside the gutter
+                let border = self.theme.table_border.start(self.depth);
+                let mut table = Vec::new();
+                self.state().table_draw(&mut table, &border)?;
+                self.states.pop();
+                let rest = self.gutter.concat();
+                let first = self.first_prefix();
+                for (i, line) in String::from_utf8_lossy(&table).lines().enumerate() {
+                    writeln!(buf, "{}{}", if i == 0 { &first } else { &rest }, line)?;
+                }
             }
-            Tag::TableHead => {
-                self.state.set_table_state(TableState::Body);
+            TagEnd::TableHead => {
+                self.state().set_table_state(TableState::Body);
             }
-            Tag::TableRow => {}
-            Tag::TableCell => {
-                self.state.table_inc_index();
+            TagEnd::TableRow => {}
+            TagEnd::TableCell => {
+                self.state().table_inc_index();
             }
-            Tag::BlockQuote => {
-                write!(buf, "{}", *RESET_COLOR)?;
+            TagEnd::BlockQuote(_) => {
+                self.flush(buf)?;
+                self.gutter.pop();
+                self.quotes.pop();
+                write!(buf, "{}", *RESET_STYLE)?;
             }
-            Tag::CodeBlock(_) => {
+            TagEnd::CodeBlock => {
                 self.write_code(buf)?;
-                self.state = State::default();
-                write!(buf, "{}\n", *RESET_COLOR)?;
-            }
-            Tag::List(Some(_)) => fresh_line(buf)?, // ol
-            Tag::List(None) => fresh_line(buf)?,
-            Tag::Item => (),
-            Tag::Emphasis => {
-                write!(buf, "{}", *RESET_STYLE)?;
+                self.states.pop();
+                write!(buf, "{}", reset(38, self.depth))?;
+                self.fresh_line(buf)?;
+            }
+            TagEnd::List(_) => {
+                self.flush(buf)?;
+                self.write_task_summary(buf)?;
+                self.states.pop();
+            }
+            TagEnd::Item => {
+                self.flush(buf)?;
+                self.flush_lead(buf)?;
+                self.gutter.pop();
+            }
+            TagEnd::Emphasis | TagEnd::Strong => self.pop_style(),
+            TagEnd::Strikethrough if self.depth == ColorDepth::NoColor => self.write_inline("~"),
+            TagEnd::Strikethrough => self.pop_style(),
+            TagEnd::Link => {
+                if self.osc8() {
+                    self.write_inline(LINK_END);
+                }
+                self.pop_style();
+                if let Some(number) = self.link.take() {
+                    if self.footnotes() {
+                        self.write_inline(format!("[{}]", number));
+                    } else if self.link_mode == Links::Inline {
+                        let dest = format!(" ({})", self.links.dest(number));
+                        self.write_inline(dest);
+                    }
+                }
             }
-            Tag::Strong => {
-                write!(buf, "{}", *RESET_STYLE)?;
+            TagEnd::Image => self.write_image(buf)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Whether links are clickable. Plain output has no escapes to do it
+    /// with, so they become footnotes.
+    fn osc8(&self) -> bool {
+        !self.plain && matches!(self.link_mode, Links::Osc8 | Links::Both)
+    }
+
+    /// Whether links are numbered and listed at the end.
+    fn footnotes(&self) -> bool {
+        match self.link_mode {
+            Links::Footnote | Links::Both => true,
+            Links::Osc8 => self.plain,
+            Links::Inline => false,
+        }
+    }
+
+    /// Writes the numbered list of link destinations, numbers aligned on
+    /// the right.
+    fn write_links<W: Write>(&self, w: &mut W) -> Result<()> {
+        if self.links.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            w,
+            "\n{}Links{}",
+            self.theme.strong.start(self.depth),
+            *RESET_STYLE
+        )?;
+        let style = self.theme.list_marker.start(self.depth);
+        let last = self.links.iter().count();
+        let width = format!("[{}]", last).len();
+        for (number, dest, title) in self.links.iter() {
+            let label = format!("[{}]", number);
+            write!(
+                w,
+                "{}{:>width$}{} ",
+                style,
+                label,
+                *RESET_STYLE,
+                width = width
+            )?;
+            if title.is_empty() {
+                writeln!(w, "{}", dest)?;
+            } else {
+                writeln!(w, "{}: {}", title, dest)?;
             }
-            Tag::Code => {
-                write!(buf, "`")?;
-                write!(buf, "{}", *RESET_STYLE)?;
+        }
+        Ok(())
+    }
+
+    fn write_rule<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+        self.flush(buf)?;
+        self.fresh_line(buf)?;
+        let width = self.inner_width();
+        let prefix = self.first_prefix();
+        writeln!(
+            buf,
+            "{}{}{}{}",
+            prefix,
+            self.theme.rule.start(self.depth),
+            "-".repeat(width),
+            *RESET_STYLE
+        )
+    }
+
+    /// Turns the marker of the current item into a checkbox. Bullets are
+    /// replaced, numbers are kept in front of it.
+    fn task(&mut self, checked: bool) {
+        self.state().count_task(checked);
+        let glyph = if checked {
+            self.glyphs.checked
+        } else {
+            self.glyphs.unchecked
+        };
+        let style = self.theme.list_marker.start(self.depth);
+        let marker = match (self.lead.take(), self.states.last()) {
+            (Some(lead), Some(State::Ol { .. })) => {
+                format!("{}{}{} {}", lead, style, glyph, *RESET_STYLE)
             }
-            Tag::Link(_, _) => {
-                write!(buf, "{}", *RESET_STYLE)?;
-                let num = self.links.len().to_string();
-                let l = "[".to_string() + &num + "]";
-                write!(buf, "{}", &l)?;
+            _ => format!("{} {} {}", style, glyph, *RESET_STYLE),
+        };
+        self.gutter.pop();
+        self.gutter.push(" ".repeat(display_width(&marker)));
+        self.lead = Some(marker);
+    }
+
+    /// Writes how many of the tasks in the list that just ended are done,
+    /// if there were any and it was asked for.
+    fn write_task_summary<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+        let (done, total) = self.state().tasks();
+        if !self.task_summary || total == 0 {
+            return Ok(());
+        }
+        writeln!(
+            buf,
+            "{}{}{}/{} tasks done{}",
+            self.gutter.concat(),
+            self.theme.list_marker.start(self.depth),
+            done,
+            total,
+            *RESET_STYLE
+        )
+    }
+
+    /// Draws the image that just ended on lines of its own, or writes a
+    /// placeholder in the text if it is remote, missing or can't be decoded.
+    fn write_image<W: Write>(&mut self, buf: &mut W) -> Result<()> {
+        let (dest, alt) = match self.image.take() {
+            Some(image) => image,
+            None => return Ok(()),
+        };
+        let drawn = match self.states.last() {
+            Some(State::Table { .. }) => None,
+            _ if dest.contains("://") || self.plain => None,
+            // half blocks are only a picture in color
+            _ if self.graphics == Graphics::Blocks && self.depth == ColorDepth::NoColor => None,
+            _ => {
+                let max = (self.inner_width() as u32, u32::from(self.term_size.1));
+                img::render(
+                    self.root.join(&*dest),
+                    self.graphics,
+                    max,
+                    self.cell_size,
+                    self.depth,
+                )
+                .ok()
             }
-            Tag::Image(_, _) => (), // shouldn't happen, handled in start
-            Tag::FootnoteDefinition(_) => {
-                fresh_line(buf)?;
+        };
+        match drawn {
+            Some(lines) => {
+                self.flush(buf)?;
+                let rest = self.gutter.concat();
+                for (i, line) in lines.iter().enumerate() {
+                    let prefix = if i == 0 {
+                        self.first_prefix()
+                    } else {
+                        rest.clone()
+                    };
+                    writeln!(buf, "{}{}", prefix, line)?;
+                }
             }
+            None => self.write_inline(format!(
+                "{}[image: {}]({}){}",
+                indexed(38, 5, self.depth),
+                alt,
+                dest,
+                reset(38, self.depth)
+            )),
         }
         Ok(())
     }
 
-    fn soft_break(&mut self) {}
+    fn soft_break(&mut self) {
+        self.write_inline(" ");
+    }
 
-    fn hard_break(&mut self) {}
+    fn hard_break(&mut self) {
+        self.write_inline("\n");
+    }
 
+    /// Writes the code block that just ended, highlighted and indented to
+    /// the enclosing containers.
     fn write_code<W: Write>(&mut self, buf: &mut W) -> Result<()> {
-        if let State::Code {
+        let mut prefix = self.first_prefix();
+        let rest = self.gutter.concat();
+        if let Some(State::Code {
             ref mut code,
             ref mut lang,
-        } = self.state
+        }) = self.states.last_mut()
         {
-            let ts = &self.theme_set.themes["Solarized (dark)"];
+            let themes = &self.theme_set.themes;
+            let ts = themes
+                .get(&self.theme.code_theme)
+                .unwrap_or_else(|| &themes["Solarized (dark)"]);
             let ps = &self.syntax_set;
 
             let syntax = if let Some(ref lang) = *lang {
                 ps.find_syntax_by_token(lang)
             } else {
-                ps.find_syntax_by_first_line(&code)
+                ps.find_syntax_by_first_line(code)
             }
             .unwrap_or_else(|| ps.find_syntax_plain_text());
 
+            // below truecolor the theme's plain text color is left to the
+            // terminal, it's rarely close to the terminal's own background
+            let plain = match self.depth {
+                ColorDepth::TrueColor => None,
+                _ => ts.settings.foreground,
+            };
             let mut h = HighlightLines::new(syntax, ts);
             for line in code.lines() {
-                let regions: Vec<(Style, &str)> = h.highlight(&line, &ps);
-                if self.truecolor {
-                    as_24_bit_terminal_escaped(buf, &regions[..], false)?;
-                } else {
-                    write_as_ansi(buf, &regions)?;
-                }
-                write!(buf, "\n")?;
+                let regions: Vec<(Style, &str)> = h.highlight(line, ps);
+                write!(buf, "{}", prefix)?;
+                prefix.clone_from(&rest);
+                write_highlighted(buf, &regions, self.depth, plain)?;
+                writeln!(buf)?;
             }
             // Clear the formatting
             write!(buf, "\x1b[0m")?;
@@ -423,72 +932,30 @@ where
     }
 }
 
-fn fresh_line<W: Write>(buf: &mut W) -> Result<()> {
-    write!(buf, "\n")?;
-    Ok(())
-}
-
-fn as_24_bit_terminal_escaped<W: Write>(w: &mut W, v: &[(Style, &str)], bg: bool) -> Result<()> {
-    for &(ref style, text) in v.iter() {
-        if bg {
-            write!(
-                w,
-                "\x1b[48;2;{};{};{}m",
-                style.background.r, style.background.g, style.background.b
-            )?;
-        }
-        write!(
-            w,
-            "\x1b[38;2;{};{};{}m{}",
-            style.foreground.r, style.foreground.g, style.foreground.b, text
-        )?;
-    }
-    write!(w, "\x1b[0m")?;
-
-    Ok(())
-}
-
-fn write_as_ansi<W: Write>(w: &mut W, regions: &[(Style, &str)]) -> Result<()> {
+/// Writes highlighted `regions` with their colors reduced to `depth`.
+/// Regions colored `plain` get the terminal's default foreground instead.
+fn write_highlighted<W: Write>(
+    w: &mut W,
+    regions: &[(Style, &str)],
+    depth: ColorDepth,
+    plain: Option<SynColor>,
+) -> Result<()> {
     for &(style, text) in regions {
-        let rgb = {
-            let fg = style.foreground;
-            (fg.r, fg.g, fg.b)
-        };
-        match rgb {
-            // base03, base02, base01, base00, base0, base1, base2, and base3
-            (0x00, 0x2b, 0x36)
-            | (0x07, 0x36, 0x42)
-            | (0x58, 0x6e, 0x75)
-            | (0x65, 0x7b, 0x83)
-            | (0x83, 0x94, 0x96)
-            | (0x93, 0xa1, 0xa1)
-            | (0xee, 0xe8, 0xd5)
-            | (0xfd, 0xf6, 0xe3) => write!(w, "{}", color::Fg(color::Reset))?,
-            (0xb5, 0x89, 0x00) => write!(w, "{}", color::Fg(color::Yellow))?, // yellow
-            (0xcb, 0x4b, 0x16) => write!(w, "{}", color::Fg(color::LightRed))?, // orange
-            (0xdc, 0x32, 0x2f) => write!(w, "{}", color::Fg(color::Red))?,    // red
-            (0xd3, 0x36, 0x82) => write!(w, "{}", color::Fg(color::Magenta))?, // magenta
-            (0x6c, 0x71, 0xc4) => write!(w, "{}", color::Fg(color::LightMagenta))?, /* violet */
-            (0x26, 0x8b, 0xd2) => write!(w, "{}", color::Fg(color::Blue))?,   // blue
-            (0x2a, 0xa1, 0x98) => write!(w, "{}", color::Fg(color::Cyan))?,   // cyan
-            (0x85, 0x99, 0x00) => write!(w, "{}", color::Fg(color::Green))?,  // green
-            (r, g, b) => panic!("Unexpected RGB colour: #{:2>0x}{:2>0x}{:2>0x}", r, g, b),
-        };
+        let fg = style.foreground;
+        if Some(fg) == plain {
+            write!(w, "{}", reset(38, depth))?;
+        } else {
+            write!(w, "{}", rgb(38, (fg.r, fg.g, fg.b), depth))?;
+        }
         let font = style.font_style;
         if font.contains(FontStyle::BOLD) {
             write!(w, "{}", style::Bold)?;
-        } else {
-            write!(w, "{}", style::NoBold)?;
         }
         if font.contains(FontStyle::ITALIC) {
             write!(w, "{}", style::Italic)?;
-        } else {
-            write!(w, "{}", style::NoItalic)?;
         }
         if font.contains(FontStyle::UNDERLINE) {
             write!(w, "{}", style::Underline)?;
-        } else {
-            write!(w, "{}", style::NoUnderline)?;
         }
         write!(w, "{}{}", text, style::Reset)?;
     }
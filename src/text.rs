@@ -0,0 +1,532 @@
+use std::{
+    fmt,
+    io::{self, Write},
+};
+use unicode_width::UnicodeWidthChar;
+
+const RESET: &str = "\x1b[m";
+
+/// Ends an OSC 8 hyperlink.
+pub const LINK_END: &str = "\x1b]8;;\x1b\\";
+
+/// Starts an OSC 8 hyperlink to `url`, the text up to `LINK_END` is
+/// clickable in terminals supporting it.
+pub fn link_start(url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\", url)
+}
+
+/// A piece of rendered text, either an escape sequence (zero width) or
+/// printable characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    Esc(&'a str),
+    Text(&'a str),
+}
+
+/// Iterator splitting a string into `Chunk`s.
+pub struct Chunks<'a> {
+    s: &'a str,
+}
+
+pub fn chunks(s: &str) -> Chunks<'_> {
+    Chunks { s }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Chunk<'a>;
+
+    fn next(&mut self) -> Option<Chunk<'a>> {
+        if self.s.is_empty() {
+            return None;
+        }
+        let len = if self.s.starts_with('\x1b') {
+            esc_len(self.s)
+        } else {
+            self.s.find('\x1b').unwrap_or(self.s.len())
+        };
+        let (head, tail) = self.s.split_at(len);
+        self.s = tail;
+        if head.starts_with('\x1b') {
+            Some(Chunk::Esc(head))
+        } else {
+            Some(Chunk::Text(head))
+        }
+    }
+}
+
+// length in bytes of the escape sequence at the start of `s`
+fn esc_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    match bytes.get(1) {
+        // CSI: parameters then a final byte in 0x40..=0x7e
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+            .map_or(bytes.len(), |i| i + 3),
+        // OSC, DCS (sixel), APC (kitty graphics), PM and SOS strings:
+        // terminated by BEL or ST (ESC \)
+        Some(b']') | Some(b'P') | Some(b'_') | Some(b'^') | Some(b'X') => {
+            let mut i = 2;
+            while i < bytes.len() {
+                match bytes[i] {
+                    0x07 => return i + 1,
+                    0x1b if bytes.get(i + 1) == Some(&b'\\') => return i + 2,
+                    _ => i += 1,
+                }
+            }
+            bytes.len()
+        }
+        Some(c) => 1 + utf8_len(*c),
+        None => 1,
+    }
+}
+
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    }
+}
+
+/// Number of terminal columns `s` occupies, ignoring escape sequences and
+/// counting wide (CJK, emoji) characters as two columns.
+pub fn display_width(s: &str) -> usize {
+    chunks(s)
+        .map(|chunk| match chunk {
+            Chunk::Text(t) => t.chars().map(char_width).sum(),
+            Chunk::Esc(_) => 0,
+        })
+        .sum()
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Breaks `text` into lines of at most `width` columns, splitting at spaces
+/// and at embedded newlines. Words longer than `width` are split mid-word.
+///
+/// Styles (SGR escapes) that are active when a line is broken are reset at
+/// the end of that line and re-applied at the start of the next one, so the
+/// caller is free to write a differently styled prefix in between.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut wrapper = Wrapper {
+        width,
+        lines: Vec::new(),
+        line: String::new(),
+        line_width: 0,
+        space: String::new(),
+        active: String::new(),
+        link: String::new(),
+    };
+    for (i, hard_line) in text.split('\n').enumerate() {
+        if i > 0 {
+            wrapper.break_line();
+        }
+        for token in tokens(hard_line) {
+            wrapper.push(token);
+        }
+    }
+    wrapper.finish()
+}
+
+enum Token<'a> {
+    Space(&'a str),
+    Word(&'a str),
+}
+
+// splits into alternating runs of spaces and words, escapes stay attached to
+// the word they precede or follow
+fn tokens(s: &str) -> Vec<Token<'_>> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    let mut idx = 0;
+    for chunk in chunks(s) {
+        match chunk {
+            Chunk::Esc(e) => {
+                if in_space == Some(true) {
+                    out.push(Token::Space(&s[start..idx]));
+                    start = idx;
+                    in_space = Some(false);
+                }
+                idx += e.len();
+            }
+            Chunk::Text(t) => {
+                for c in t.chars() {
+                    let is_space = c == ' ';
+                    if in_space.is_some_and(|sp| sp != is_space) {
+                        out.push(if is_space {
+                            Token::Word(&s[start..idx])
+                        } else {
+                            Token::Space(&s[start..idx])
+                        });
+                        start = idx;
+                    }
+                    in_space = Some(is_space);
+                    idx += c.len_utf8();
+                }
+            }
+        }
+    }
+    if start < s.len() {
+        out.push(if in_space == Some(true) {
+            Token::Space(&s[start..])
+        } else {
+            Token::Word(&s[start..])
+        });
+    }
+    out
+}
+
+struct Wrapper {
+    width: usize,
+    lines: Vec<String>,
+    line: String,
+    line_width: usize,
+    // whitespace waiting to be written before the next word
+    space: String,
+    // SGR escapes in effect since the last full reset
+    active: String,
+    // OSC 8 hyperlink the text is in, closed and reopened around line breaks
+    link: String,
+}
+
+impl Wrapper {
+    fn push(&mut self, token: Token<'_>) {
+        match token {
+            Token::Space(s) => self.space.push_str(s),
+            Token::Word(w) => {
+                let space_width = display_width(&self.space);
+                let word_width = display_width(w);
+                if self.line_width > 0 && self.line_width + space_width + word_width > self.width {
+                    self.break_line();
+                } else {
+                    self.line_width += space_width;
+                    self.line.push_str(&self.space);
+                }
+                self.space.clear();
+                self.push_word(w);
+            }
+        }
+    }
+
+    fn push_word(&mut self, w: &str) {
+        for chunk in chunks(w) {
+            match chunk {
+                Chunk::Esc(e) => {
+                    self.line.push_str(e);
+                    if e == RESET || e == "\x1b[0m" {
+                        self.active.clear();
+                    } else if e.starts_with("\x1b[") && e.ends_with('m') {
+                        self.active.push_str(e);
+                    } else if e == LINK_END {
+                        self.link.clear();
+                    } else if e.starts_with("\x1b]8;") {
+                        self.link = e.to_string();
+                    }
+                }
+                Chunk::Text(t) => {
+                    for c in t.chars() {
+                        let cw = char_width(c);
+                        if self.line_width > 0 && self.line_width + cw > self.width {
+                            self.break_line();
+                        }
+                        self.line.push(c);
+                        self.line_width += cw;
+                    }
+                }
+            }
+        }
+    }
+
+    fn break_line(&mut self) {
+        if !self.link.is_empty() {
+            self.line.push_str(LINK_END);
+        }
+        if !self.active.is_empty() {
+            self.line.push_str(RESET);
+        }
+        let line = std::mem::replace(&mut self.line, self.active.clone() + &self.link);
+        self.lines.push(line);
+        self.line_width = 0;
+        self.space.clear();
+    }
+
+    fn finish(self) -> Vec<String> {
+        let mut lines = self.lines;
+        lines.push(self.line);
+        lines
+    }
+}
+
+/// A line of rendered output and the number of columns it takes up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// the line with its escape sequences, without the newline
+    pub text: String,
+    /// columns the line takes up on screen
+    pub width: usize,
+}
+
+impl Line {
+    pub fn new(text: String) -> Line {
+        let width = display_width(&text);
+        Line { text, width }
+    }
+
+    /// The text without escape sequences.
+    pub fn plain(&self) -> String {
+        strip_escapes(&self.text)
+    }
+
+    /// The text followed by enough spaces to take up `width` columns, as is
+    /// if it's that wide already.
+    pub fn padded(&self, width: usize) -> String {
+        let pad = width.saturating_sub(self.width);
+        format!("{}{}", self.text, " ".repeat(pad))
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Splits rendered output into `Line`s.
+pub fn lines(s: &str) -> Vec<Line> {
+    s.lines().map(|line| Line::new(line.to_string())).collect()
+}
+
+/// `s` with all escape sequences removed.
+pub fn strip_escapes(s: &str) -> String {
+    chunks(s)
+        .filter_map(|chunk| match chunk {
+            Chunk::Text(t) => Some(t),
+            Chunk::Esc(_) => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StripState {
+    Text,
+    Esc,
+    /// inside a CSI sequence, until its final byte
+    Csi,
+    /// inside an OSC, DCS, APC, PM or SOS string, until BEL or ST
+    Str,
+    /// ESC inside a string, the start of ST
+    StrEsc,
+}
+
+/// Writer dropping the escape sequences written through it, if `enabled`.
+/// Sequences may be split across writes.
+pub struct Strip<W> {
+    inner: W,
+    enabled: bool,
+    state: StripState,
+}
+
+impl<W: Write> Strip<W> {
+    pub fn new(inner: W, enabled: bool) -> Strip<W> {
+        Strip {
+            inner,
+            enabled,
+            state: StripState::Text,
+        }
+    }
+}
+
+impl<W: Write> Write for Strip<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.enabled {
+            return self.inner.write(buf);
+        }
+        let mut kept = Vec::with_capacity(buf.len());
+        for &b in buf {
+            self.state = match (self.state, b) {
+                (StripState::Text, 0x1b) => StripState::Esc,
+                (StripState::Text, _) => {
+                    kept.push(b);
+                    StripState::Text
+                }
+                (StripState::Esc, b'[') => StripState::Csi,
+                (StripState::Esc, b']')
+                | (StripState::Esc, b'P')
+                | (StripState::Esc, b'_')
+                | (StripState::Esc, b'^')
+                | (StripState::Esc, b'X') => StripState::Str,
+                (StripState::Esc, _) => StripState::Text,
+                (StripState::Csi, 0x40..=0x7e) => StripState::Text,
+                (StripState::Csi, _) => StripState::Csi,
+                (StripState::Str, 0x07) => StripState::Text,
+                (StripState::Str, 0x1b) => StripState::StrEsc,
+                (StripState::Str, _) => StripState::Str,
+                (StripState::StrEsc, b'\\') => StripState::Text,
+                (StripState::StrEsc, _) => StripState::Str,
+            };
+        }
+        self.inner.write_all(&kept)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer shifting every line written through it right by `margin` columns.
+/// Empty lines are left empty.
+pub struct Indent<W> {
+    inner: W,
+    margin: usize,
+    line_start: bool,
+}
+
+impl<W: Write> Indent<W> {
+    pub fn new(inner: W, margin: usize) -> Indent<W> {
+        Indent {
+            inner,
+            margin,
+            line_start: true,
+        }
+    }
+}
+
+impl<W: Write> Write for Indent<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.margin == 0 {
+            return self.inner.write(buf);
+        }
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if self.line_start && line != b"\n" {
+                write!(self.inner, "{:1$}", "", self.margin)?;
+            }
+            self.inner.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_escapes_have_no_width() {
+        let sixel = "\x1bP0;1;0q\"1;1;2;2#1;2;100;0;0#1~~-\x1b\\";
+        let kitty = "\x1b_Ga=T,q=2,f=32,s=1,v=1;AAAA/w==\x1b\\";
+        for image in [sixel, kitty, "\x1b^private\x07", "\x1bXstring\x1b\\"] {
+            let s = format!("a{}b", image);
+            assert_eq!(display_width(&s), 2, "{:?}", image);
+            assert_eq!(strip_escapes(&s), "ab");
+            assert_eq!(
+                chunks(&s).collect::<Vec<_>>(),
+                [Chunk::Text("a"), Chunk::Esc(image), Chunk::Text("b")]
+            );
+        }
+    }
+
+    #[test]
+    fn strip_drops_string_escapes_split_across_writes() {
+        let mut out = Vec::new();
+        {
+            let mut strip = Strip::new(&mut out, true);
+            strip.write_all(b"a\x1b_Gf=32;AA").unwrap();
+            strip.write_all(b"AA\x1b\\b\x1bPq#1~\x1b").unwrap();
+            strip.write_all(b"\\c").unwrap();
+        }
+        assert_eq!(out, b"abc");
+    }
+
+    #[test]
+    fn line_width_skips_escapes() {
+        let sgr = Line::new("\x1b[1;31mbold\x1b[m red".to_string());
+        assert_eq!(sgr.width, 8);
+        assert_eq!(sgr.plain(), "bold red");
+        assert_eq!(sgr.padded(10), "\x1b[1;31mbold\x1b[m red  ");
+
+        let link = Line::new(format!(
+            "see {}here{}.",
+            link_start("http://x.org/"),
+            LINK_END
+        ));
+        assert_eq!(link.width, 9);
+        assert_eq!(link.plain(), "see here.");
+
+        let image =
+            Line::new("\x1b_Ga=T,q=2,f=32,s=1,v=1,c=1,r=1,m=0;AAAA/w==\x1b\\ cat".to_string());
+        assert_eq!(image.width, 4);
+        assert_eq!(image.plain(), " cat");
+    }
+
+    #[test]
+    fn line_width_counts_wide_characters_twice() {
+        assert_eq!(Line::new("日本語".to_string()).width, 6);
+        assert_eq!(Line::new("a\x1b[32m語\x1b[m!".to_string()).width, 4);
+        let widths: Vec<usize> = lines("ab\n\x1b[1m日本\x1b[m\n")
+            .iter()
+            .map(|l| l.width)
+            .collect();
+        assert_eq!(widths, [2, 4]);
+    }
+
+    #[test]
+    fn wrap_breaks_at_spaces_and_newlines() {
+        assert_eq!(wrap("the quick brown fox", 10), ["the quick", "brown fox"]);
+        assert_eq!(wrap("one\ntwo three", 20), ["one", "two three"]);
+        assert_eq!(wrap("a\n\nb", 20), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn wrap_splits_long_words() {
+        assert_eq!(wrap("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("to abcdefgh", 5), ["to", "abcde", "fgh"]);
+    }
+
+    #[test]
+    fn wrap_keeps_wide_characters_whole() {
+        // three two-column characters don't fit in five columns
+        assert_eq!(wrap("日本語", 5), ["日本", "語"]);
+        assert_eq!(wrap("a 日本語", 4), ["a", "日本", "語"]);
+        for line in wrap("日本語の文章を折り返す", 7) {
+            assert!(display_width(&line) <= 7, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn wrap_reopens_styles_on_the_next_line() {
+        let bold = "\x1b[1m";
+        let lines = wrap(&format!("{}bold text here{} plain", bold, RESET), 10);
+        assert_eq!(
+            lines,
+            [
+                format!("{}bold text{}", bold, RESET),
+                format!("{}here{} plain", bold, RESET),
+            ]
+        );
+    }
+
+    #[test]
+    fn wrap_reopens_links_on_the_next_line() {
+        let start = link_start("http://x.org/");
+        let lines = wrap(&format!("see {}the docs{} now", start, LINK_END), 8);
+        assert_eq!(
+            lines,
+            [
+                format!("see {}the{}", start, LINK_END),
+                format!("{}docs{} now", start, LINK_END),
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    color::{self, ColorDepth},
+    MDResult, MarkdownError,
+};
+use serde::Deserialize;
+use std::{convert::TryFrom, fs, path::Path};
+
+/// A terminal color, one of the 256 palette entries or an RGB value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Color {
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+const NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright-black",
+    "bright-red",
+    "bright-green",
+    "bright-yellow",
+    "bright-blue",
+    "bright-magenta",
+    "bright-cyan",
+    "bright-white",
+];
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    /// Parses a color name ("red", "bright-blue"), a palette index ("208") or
+    /// a hex RGB value ("#ff8700").
+    fn try_from(s: String) -> Result<Color, String> {
+        let name = s.trim().to_lowercase().replace('_', "-");
+        if let Some(i) = NAMES.iter().position(|&n| n == name) {
+            return Ok(Color::Ansi(i as u8));
+        }
+        if let Ok(i) = name.parse() {
+            return Ok(Color::Ansi(i));
+        }
+        let hex = name.trim_start_matches('#');
+        if hex.len() == 6 {
+            if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+                return Ok(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+            }
+        }
+        Err(format!("invalid color '{}'", s))
+    }
+}
+
+impl Color {
+    fn escape(self, layer: u8, depth: ColorDepth) -> String {
+        match self {
+            Color::Ansi(i) => color::indexed(layer, i, depth),
+            Color::Rgb(r, g, b) => color::rgb(layer, (r, g, b), depth),
+        }
+    }
+}
+
+/// How one element of the document is drawn.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl Style {
+    fn fg(color: u8) -> Style {
+        Style {
+            fg: Some(Color::Ansi(color)),
+            ..Style::default()
+        }
+    }
+
+    fn bold(mut self) -> Style {
+        self.bold = true;
+        self
+    }
+
+    fn italic() -> Style {
+        Style {
+            italic: true,
+            ..Style::default()
+        }
+    }
+
+    fn underline() -> Style {
+        Style {
+            underline: true,
+            ..Style::default()
+        }
+    }
+
+    fn strikethrough() -> Style {
+        Style {
+            strikethrough: true,
+            ..Style::default()
+        }
+    }
+
+    /// Escapes switching the terminal to this style, with colors reduced to
+    /// what's available at `depth`.
+    pub fn start(&self, depth: ColorDepth) -> String {
+        let mut s = String::new();
+        if let Some(fg) = self.fg {
+            s.push_str(&fg.escape(38, depth));
+        }
+        if let Some(bg) = self.bg {
+            s.push_str(&bg.escape(48, depth));
+        }
+        if self.bold {
+            s.push_str("\x1b[1m");
+        }
+        if self.italic {
+            s.push_str("\x1b[3m");
+        }
+        if self.underline {
+            s.push_str("\x1b[4m");
+        }
+        if self.strikethrough {
+            s.push_str("\x1b[9m");
+        }
+        s
+    }
+}
+
+/// Styles for every element of the document. Theme files only need to list
+/// the elements they change, the rest come from the dark preset.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub h1: Style,
+    pub h2: Style,
+    pub h3: Style,
+    pub h4: Style,
+    pub h5: Style,
+    pub h6: Style,
+    pub emphasis: Style,
+    pub strong: Style,
+    pub strikethrough: Style,
+    pub code: Style,
+    pub link: Style,
+    pub quote: Style,
+    pub list_marker: Style,
+    pub rule: Style,
+    pub table_border: Style,
+    /// syntect theme code blocks are highlighted with
+    pub code_theme: String,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Theme {
+        Theme {
+            h1: Style::fg(1).bold(),
+            h2: Style::fg(1),
+            h3: Style::fg(3).bold(),
+            h4: Style::fg(3),
+            h5: Style::fg(6),
+            h6: Style::fg(6),
+            emphasis: Style::italic(),
+            strong: Style::default().bold(),
+            strikethrough: Style::strikethrough(),
+            code: Style::italic(),
+            link: Style::underline(),
+            quote: Style::fg(2),
+            list_marker: Style::fg(1),
+            rule: Style::default(),
+            table_border: Style::default(),
+            code_theme: "Solarized (dark)".to_string(),
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            h1: Style::fg(4).bold(),
+            h2: Style::fg(4),
+            h3: Style::fg(5).bold(),
+            h4: Style::fg(5),
+            h5: Style::fg(6),
+            h6: Style::fg(6),
+            quote: Style::fg(8),
+            list_marker: Style::fg(4),
+            rule: Style::fg(8),
+            table_border: Style::fg(8),
+            code_theme: "Solarized (light)".to_string(),
+            ..Theme::dark()
+        }
+    }
+
+    /// A preset by name ("dark" or "light"), or else a theme file, read as
+    /// JSON if its extension is `.json` and as TOML otherwise.
+    pub fn load(name: &str) -> MDResult<Theme> {
+        match name {
+            "dark" => return Ok(Theme::dark()),
+            "light" => return Ok(Theme::light()),
+            _ => {}
+        }
+        let path = Path::new(name);
+        let data =
+            fs::read_to_string(path).map_err(|e| MarkdownError::File(path.to_path_buf(), e))?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Ok(serde_json::from_str(&data)?)
+        } else {
+            Ok(toml::from_str(&data)?)
+        }
+    }
+
+    /// Style of a heading of `level` (1 to 6).
+    pub fn heading(&self, level: i32) -> &Style {
+        match level {
+            1 => &self.h1,
+            2 => &self.h2,
+            3 => &self.h3,
+            4 => &self.h4,
+            5 => &self.h5,
+            _ => &self.h6,
+        }
+    }
+}
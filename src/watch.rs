@@ -0,0 +1,71 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Polls a file for changes, going by its modification time and size. This
+/// also notices the file going missing and coming back, as it does while an
+/// editor saves by renaming a new copy over it.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    path: PathBuf,
+    seen: Option<(SystemTime, u64)>,
+}
+
+impl Watcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> Watcher {
+        let mut watcher = Watcher {
+            path: path.as_ref().to_path_buf(),
+            seen: None,
+        };
+        watcher.seen = watcher.stat();
+        watcher
+    }
+
+    /// Whether the file changed since the last call, or since the watcher
+    /// was made.
+    pub fn changed(&mut self) -> bool {
+        let now = self.stat();
+        if now == self.seen {
+            false
+        } else {
+            self.seen = now;
+            true
+        }
+    }
+
+    fn stat(&self) -> Option<(SystemTime, u64)> {
+        let meta = fs::metadata(&self.path).ok()?;
+        Some((meta.modified().ok()?, meta.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Watcher;
+    use std::{env, fs, process};
+
+    #[test]
+    fn notices_changes_removal_and_recreation() {
+        let dir = env::temp_dir().join(format!("mdt-watch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("doc.md");
+        fs::write(&path, "# One\n").unwrap();
+
+        let mut watcher = Watcher::new(&path);
+        assert!(!watcher.changed());
+        // a different size, in case the clock is too coarse to move mtime
+        fs::write(&path, "# One\n\nMore.\n").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        fs::write(&path, "# Two\n").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}